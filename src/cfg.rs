@@ -0,0 +1,369 @@
+use crate::quaternion::Quaternion;
+use std::fmt::Display;
+
+// A maximal run of quaternions that is only entered at its first and left
+// after its last. Jump targets inside a block are block ids, not indices.
+#[derive(Clone, Debug, Default)]
+pub struct BasicBlock {
+    pub quaternions: Vec<Quaternion>,
+    pub succ: Vec<usize>,
+    pub pred: Vec<usize>,
+}
+
+// Control-flow graph of one procedure. Blocks keep the order of the
+// quaternions they came from, so a block without a jump at its end falls
+// through to the next one. `exit` is an empty last block standing for the
+// end of the procedure, which `ret`s and jumps past the last quaternion go to.
+#[derive(Clone, Debug)]
+pub struct Cfg {
+    pub blocks: Vec<BasicBlock>,
+    pub exit: usize,
+}
+
+pub fn is_jump(op: &str) -> bool {
+    op == "j" || op == "jnz" || op == "jez"
+}
+
+impl Cfg {
+    pub fn new(quads: &[Quaternion]) -> Self {
+        let n = quads.len();
+
+        // leaders: the first quaternion, jump targets and whatever follows a
+        // jump, a `jtab` or a `ret`
+        let mut leader = vec![false; n + 1];
+        leader[0] = true;
+        leader[n] = true;
+        for (i, q) in quads.iter().enumerate() {
+            if is_jump(&q.op) {
+                leader[q.result.parse::<usize>().unwrap()] = true;
+            }
+            if is_jump(&q.op) || q.op == "jtab" || q.op == "ret" {
+                leader[i + 1] = true;
+            }
+        }
+
+        // `block[i]` is the block starting at leader `i`
+        let mut block = vec![0; n + 1];
+        let mut blocks: Vec<BasicBlock> = Vec::new();
+        for i in 0..=n {
+            if leader[i] {
+                block[i] = blocks.len();
+                blocks.push(BasicBlock::default());
+            }
+            if i < n {
+                blocks.last_mut().unwrap().quaternions.push(quads[i].clone());
+            }
+        }
+        let exit = blocks.len() - 1;
+
+        let mut end = 0;
+        for id in 0..exit {
+            end += blocks[id].quaternions.len();
+            let last = blocks[id].quaternions.last_mut().unwrap();
+            let succ = match last.op.as_str() {
+                "j" => vec![block[last.result.parse::<usize>().unwrap()]],
+                "jnz" | "jez" => vec![block[last.result.parse::<usize>().unwrap()], block[end]],
+                // the entries and the out-of-range jump follow the `jtab`
+                "jtab" => {
+                    let (min, max): (i64, i64) = (last.arg2.parse().unwrap(), last.result.parse().unwrap());
+                    (end..end + max.abs_diff(min) as usize + 2).map(|i| block[i]).collect()
+                }
+                "ret" => vec![exit],
+                _ => vec![block[end]],
+            };
+            if is_jump(&last.op) {
+                last.result = succ[0].to_string();
+            }
+            for i in succ {
+                if !blocks[id].succ.contains(&i) {
+                    blocks[id].succ.push(i);
+                    blocks[i].pred.push(id);
+                }
+            }
+        }
+
+        Cfg { blocks, exit }
+    }
+
+    // A copy with an empty block put before the entry, so that nothing
+    // jumps back to the entry.
+    pub fn with_entry(&self) -> Cfg {
+        let mut cfg = self.clone();
+        for block in cfg.blocks.iter_mut() {
+            block.succ.iter_mut().chain(block.pred.iter_mut()).for_each(|i| *i += 1);
+            for q in block.quaternions.iter_mut().filter(|q| is_jump(&q.op)) {
+                q.result = (q.result.parse::<usize>().unwrap() + 1).to_string();
+            }
+        }
+        cfg.blocks[0].pred.insert(0, 0);
+        cfg.blocks.insert(0, BasicBlock { succ: vec![1], ..Default::default() });
+        cfg.exit += 1;
+        cfg
+    }
+
+    // Recomputes blocks and edges after quaternions were changed or removed.
+    pub fn rebuild(&mut self) {
+        *self = Cfg::new(&self.linearize());
+    }
+
+    // The blocks a `jtab` jumps into by position, which must stay single
+    // `j`s laid out right after it.
+    pub fn jump_table_entries(&self) -> Vec<usize> {
+        let mut entries = Vec::new();
+        for block in self.blocks.iter() {
+            if block.quaternions.last().is_some_and(|q| q.op == "jtab") {
+                entries.extend(block.succ.iter());
+            }
+        }
+        entries
+    }
+
+    // Lays the blocks out in order again, turning block ids back into
+    // quaternion indices; an empty block's id stands for whatever follows it.
+    pub fn linearize(&self) -> Vec<Quaternion> {
+        let mut start = Vec::new();
+        let mut n = 0;
+        for block in self.blocks.iter() {
+            start.push(n);
+            n += block.quaternions.len();
+        }
+        let mut quads = Vec::new();
+        for block in self.blocks.iter() {
+            for q in block.quaternions.iter() {
+                let mut q = q.clone();
+                if is_jump(&q.op) {
+                    q.result = start[q.result.parse::<usize>().unwrap()].to_string();
+                }
+                quads.push(q);
+            }
+        }
+        quads
+    }
+}
+
+// Immediate dominators of a graph's blocks; the entry and the blocks it
+// does not reach have none.
+pub struct Dominators {
+    pub idom: Vec<Option<usize>>,
+}
+
+impl Dominators {
+    pub fn dominates(&self, a: usize, mut b: usize) -> bool {
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idom[b] {
+                Some(i) => b = i,
+                None => return false,
+            }
+        }
+    }
+
+    // The blocks each block immediately dominates.
+    pub fn children(&self) -> Vec<Vec<usize>> {
+        let mut children = vec![Vec::new(); self.idom.len()];
+        for (id, idom) in self.idom.iter().enumerate() {
+            if let Some(i) = idom {
+                children[*i].push(id);
+            }
+        }
+        children
+    }
+
+    // The dominance frontier of each block: where its dominance ends, at
+    // blocks it does not strictly dominate but reaches in one step.
+    pub fn frontiers(&self, cfg: &Cfg) -> Vec<Vec<usize>> {
+        let mut frontiers: Vec<Vec<usize>> = vec![Vec::new(); self.idom.len()];
+        for (id, block) in cfg.blocks.iter().enumerate().filter(|(_, b)| b.pred.len() > 1) {
+            for &p in block.pred.iter().filter(|p| self.dominates(0, **p)) {
+                let mut runner = p;
+                while Some(runner) != self.idom[id] {
+                    if !frontiers[runner].contains(&id) {
+                        frontiers[runner].push(id);
+                    }
+                    match self.idom[runner] {
+                        Some(i) => runner = i,
+                        None => break,
+                    }
+                }
+            }
+        }
+        frontiers
+    }
+}
+
+// A natural loop: its header and every block that reaches a back edge to
+// the header without passing through it, in ascending order.
+pub struct Loop {
+    pub header: usize,
+    pub blocks: Vec<usize>,
+}
+
+impl Cfg {
+    // The blocks reachable from the entry, each before its successors
+    // except along back edges.
+    pub fn reverse_postorder(&self) -> Vec<usize> {
+        let mut seen = vec![false; self.blocks.len()];
+        let mut order = Vec::new();
+        let mut stack = vec![(0, 0)];
+        seen[0] = true;
+        while let Some((id, i)) = stack.pop() {
+            match self.blocks[id].succ.get(i) {
+                Some(&succ) => {
+                    stack.push((id, i + 1));
+                    if !seen[succ] {
+                        seen[succ] = true;
+                        stack.push((succ, 0));
+                    }
+                }
+                None => order.push(id),
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    // Cooper, Harvey and Kennedy's iterative algorithm.
+    pub fn dominators(&self) -> Dominators {
+        let order = self.reverse_postorder();
+        let mut rank = vec![usize::MAX; self.blocks.len()];
+        for (i, id) in order.iter().enumerate() {
+            rank[*id] = i;
+        }
+        let mut idom: Vec<Option<usize>> = vec![None; self.blocks.len()];
+        idom[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &id in order.iter().skip(1) {
+                let mut new: Option<usize> = None;
+                for &p in self.blocks[id].pred.iter().filter(|p| idom[**p].is_some()) {
+                    new = Some(match new {
+                        None => p,
+                        Some(mut a) => {
+                            let mut b = p;
+                            while a != b {
+                                while rank[a] > rank[b] {
+                                    a = idom[a].unwrap();
+                                }
+                                while rank[b] > rank[a] {
+                                    b = idom[b].unwrap();
+                                }
+                            }
+                            a
+                        }
+                    });
+                }
+                if idom[id] != new {
+                    idom[id] = new;
+                    changed = true;
+                }
+            }
+        }
+        idom[0] = None;
+        Dominators { idom }
+    }
+
+    // Loops sharing a header are merged; inner loops come first.
+    pub fn loops(&self, dom: &Dominators) -> Vec<Loop> {
+        let mut found: Vec<Loop> = Vec::new();
+        for (id, block) in self.blocks.iter().enumerate() {
+            for &header in block.succ.iter().filter(|h| dom.dominates(**h, id)) {
+                let mut blocks = vec![header];
+                let mut stack = vec![id];
+                while let Some(b) = stack.pop() {
+                    if !blocks.contains(&b) {
+                        blocks.push(b);
+                        stack.extend(self.blocks[b].pred.iter());
+                    }
+                }
+                match found.iter_mut().find(|l| l.header == header) {
+                    Some(l) => {
+                        blocks.retain(|b| !l.blocks.contains(b));
+                        l.blocks.extend(blocks);
+                    }
+                    None => found.push(Loop { header, blocks }),
+                }
+            }
+        }
+        for l in found.iter_mut() {
+            l.blocks.sort();
+        }
+        found.sort_by_key(|l| l.blocks.len());
+        found
+    }
+}
+
+impl Cfg {
+    // Graphviz digraph with one node per block; the edges of a conditional
+    // jump are labeled with the value of its condition, those of a `jtab`
+    // with the label each entry stands for.
+    pub fn dot(&self, name: &str) -> String {
+        let mut s = format!("digraph \"{}\" {{\n", name);
+        s.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        for (id, block) in self.blocks.iter().enumerate() {
+            if id == self.exit {
+                s.push_str(&format!("    B{} [label=\"exit\", shape=ellipse];\n", id));
+                continue;
+            }
+            let mut label = format!("B{}\\l", id);
+            for i in block.quaternions.iter() {
+                let q = format!("({}, {}, {}, {})", i.op, i.arg1, i.arg2, i.result);
+                label.push_str(&q.replace('\\', "\\\\").replace('"', "\\\""));
+                label.push_str("\\l");
+            }
+            s.push_str(&format!("    B{} [label=\"{}\"];\n", id, label));
+        }
+        for (id, block) in self.blocks.iter().enumerate() {
+            let last = match block.quaternions.last() {
+                Some(last) => last,
+                None => continue,
+            };
+            let labels: Vec<String> = match last.op.as_str() {
+                "jnz" | "jez" if block.succ.len() == 2 => {
+                    let taken = last.op == "jnz";
+                    vec![taken.to_string(), (!taken).to_string()]
+                }
+                "jtab" => {
+                    let min: i64 = last.arg2.parse().unwrap();
+                    let mut labels: Vec<String> =
+                        (0..block.succ.len() as i64 - 1).map(|i| (min + i).to_string()).collect();
+                    labels.push("else".to_string());
+                    labels
+                }
+                _ => Vec::new(),
+            };
+            for (i, succ) in block.succ.iter().enumerate() {
+                match labels.get(i) {
+                    Some(label) => {
+                        s.push_str(&format!("    B{} -> B{} [label=\"{}\"];\n", id, succ, label))
+                    }
+                    None => s.push_str(&format!("    B{} -> B{};\n", id, succ)),
+                }
+            }
+        }
+        s.push_str("}\n");
+        s
+    }
+}
+
+impl Display for Cfg {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let ids = |list: &[usize]| match list.is_empty() {
+            true => "-".to_string(),
+            false => list.iter().map(|i| format!("B{}", i)).collect::<Vec<_>>().join(" "),
+        };
+        for (id, block) in self.blocks.iter().enumerate() {
+            if id == self.exit {
+                writeln!(f, "B{}: exit, pred: {}", id, ids(&block.pred))?;
+                continue;
+            }
+            writeln!(f, "B{}: pred: {}, succ: {}", id, ids(&block.pred), ids(&block.succ))?;
+            for i in block.quaternions.iter() {
+                writeln!(f, "    ({}, {}, {}, {})", i.op, i.arg1, i.arg2, i.result)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,538 @@
+// A node of a basic block's DAG: a leaf for the value a name or literal
+// has on entry, or once a call or an aliased store may have changed it, or
+// an operation on other nodes. `labels` are the names that were given its
+// value along the way.
+#[derive(Clone, Debug)]
+struct DagNode {
+    pub op: String,
+    pub args: Vec<usize>,
+    pub ty: Option<String>,
+    pub labels: Vec<String>,
+}
+
+// A point of the block that must stay where it is: the `sync` names are
+// brought up to date, then `q` is emitted reading `args`. It may write a
+// name itself, and the names in `kill` have a new leaf after it.
+#[derive(Clone, Default)]
+struct Event {
+    q: Option<Quaternion>,
+    args: Vec<usize>,
+    sync: Vec<(String, usize)>,
+    write: Option<(String, usize)>,
+    kill: Vec<(String, usize)>,
+}
+
+// The DAG of one basic block and the quaternions regenerated from it
+// (dragon book, 8.5). Each node is computed once, when a side effect, a
+// variable or a temp read after the block first needs it, and not at all
+// if nothing does.
+struct Dag {
+    pub nodes: Vec<DagNode>,
+    pub quaternions: Vec<Quaternion>,
+    // the name each node was first computed into; storing into a variable
+    // converts the value, so a conversion is computed there again
+    origin: Vec<Option<String>>,
+    fixed: Vec<bool>,
+    keys: HashMap<(String, Vec<usize>, Option<String>), usize>,
+    // node each name has the value of
+    names: HashMap<String, usize>,
+    // names assigned in the block, in the order of their last assignment
+    order: Vec<String>,
+    events: Vec<Event>,
+    // node each name really holds while regenerating
+    held: BTreeMap<String, usize>,
+    // the nodes the events need, the ones from `at` on still to come, and
+    // where in them each name is to be brought up to date
+    demands: Vec<usize>,
+    at: usize,
+    stores: Vec<(usize, String, usize)>,
+    // nodes whose operands are being computed, and names about to be
+    // overwritten
+    stack: Vec<usize>,
+    busy: Vec<String>,
+}
+
+fn is_terminator(op: &str) -> bool {
+    is_jump(op) || op == "jtab" || op == "ret"
+}
+
+fn is_commutative(op: &str) -> bool {
+    matches!(op, "+" | "*" | "==" | "<>" | "and" | "or")
+}
+
+impl Dag {
+    // `live_out` are the temps read after the block, which must hold their
+    // values when it ends. New temps are numbered after `temps`.
+    pub fn new(block: &[Quaternion], live_out: &HashSet<String>, scope: &Scope, temps: &mut usize) -> Self {
+        let mut dag = Dag {
+            nodes: Vec::new(),
+            quaternions: Vec::new(),
+            origin: Vec::new(),
+            fixed: Vec::new(),
+            keys: HashMap::new(),
+            names: HashMap::new(),
+            order: Vec::new(),
+            events: Vec::new(),
+            held: BTreeMap::new(),
+            demands: Vec::new(),
+            at: 0,
+            stores: Vec::new(),
+            stack: Vec::new(),
+            busy: Vec::new(),
+        };
+        let kept = |name: &str| !name.starts_with('@') || live_out.contains(name);
+        for q in block.iter() {
+            let args: Vec<usize> = operands(q).into_iter().map(|i| dag.leaf(i, scope)).collect();
+            if is_terminator(&q.op) {
+                let sync = dag.sync(kept);
+                dag.events.push(Event { q: Some(q.clone()), args, sync, ..Default::default() });
+                continue;
+            }
+            if q.op == "call" {
+                let sync = dag.sync(|name| !name.starts_with('@'));
+                let kill = dag.kill(|name| !name.starts_with('@'), scope);
+                dag.events.push(Event { q: Some(q.clone()), sync, kill, ..Default::default() });
+                continue;
+            }
+            let result = match def(q) {
+                Some(result) => result.to_string(),
+                None => {
+                    let mut event = Event { q: Some(q.clone()), args, ..Default::default() };
+                    // a store into an array an aliased name may be bound to
+                    if assigns_aliased(q, scope) {
+                        event.sync = dag.sync(|name| scope.aliased(name));
+                        event.kill = dag.kill(|name| scope.aliased(name), scope);
+                    }
+                    dag.events.push(event);
+                    continue;
+                }
+            };
+            let aliased = scope.aliased(&result);
+            let others = |name: &str| name != result && scope.aliased(name);
+            let ty = match result.starts_with('@') {
+                true => None,
+                false => scope.ty(&result).map(|i| i.to_string()),
+            };
+            let node = match q.op.as_str() {
+                "=" if result.starts_with('@') || ty == dag.nodes[args[0]].ty => args[0],
+                "=" => {
+                    let node = dag.node("=", args, ty, &result);
+                    dag.fixed[node] = true;
+                    node
+                }
+                "=[]" | "read" | "retval" => {
+                    let node = dag.opaque(&q.op, args.clone(), ty);
+                    let write = Some((result.clone(), node));
+                    let mut event = Event { q: Some(q.clone()), args, write, ..Default::default() };
+                    // an element may be an aliased name's slot
+                    if aliased || (q.op == "=[]" && scope.aliased(&q.arg1)) {
+                        event.sync = dag.sync(others);
+                    }
+                    if aliased {
+                        event.kill = dag.kill(others, scope);
+                    }
+                    dag.events.push(event);
+                    dag.assign(&result, node);
+                    continue;
+                }
+                op => {
+                    let node_ty = dag.type_of(op, &args);
+                    let node = match !result.starts_with('@') && ty != node_ty {
+                        true => {
+                            let node = dag.opaque(op, args, ty);
+                            dag.origin[node] = Some(result.clone());
+                            dag.fixed[node] = true;
+                            node
+                        }
+                        false => dag.node(op, args, node_ty, &result),
+                    };
+                    // a division that may fail happens where it was
+                    let divisor = dag.nodes[node].args.get(1).map(|i| &dag.nodes[*i].op);
+                    let safe = divisor.is_some_and(|i| is_literal(i) && literal(i).is_some_and(|v| v.is_true()));
+                    if (op == "/" || op == "%") && !safe {
+                        dag.events.push(Event { args: vec![node], ..Default::default() });
+                    }
+                    node
+                }
+            };
+            if aliased {
+                let mut sync = dag.sync(others);
+                sync.push((result.clone(), node));
+                let kill = dag.kill(others, scope);
+                dag.events.push(Event { sync, kill, ..Default::default() });
+            }
+            dag.assign(&result, node);
+        }
+        if !block.last().is_some_and(|q| is_terminator(&q.op)) {
+            let sync = dag.sync(kept);
+            dag.events.push(Event { sync, ..Default::default() });
+        }
+        dag.generate(scope, temps);
+        dag
+    }
+
+    // The node for a name or literal read here, a new leaf if not known yet.
+    fn leaf(&mut self, name: &str, scope: &Scope) -> usize {
+        if let Some(node) = self.names.get(name) {
+            return *node;
+        }
+        let ty = match is_literal(name) {
+            true if name == "true" || name == "false" => Some("boolean"),
+            true if name.contains('.') => Some("float"),
+            true => Some("integer"),
+            false => scope.ty(name),
+        };
+        let node = self.opaque(name, Vec::new(), ty.map(|i| i.to_string()));
+        self.names.insert(name.to_string(), node);
+        if !is_literal(name) {
+            self.held.insert(name.to_string(), node);
+        }
+        node
+    }
+
+    fn node(&mut self, op: &str, args: Vec<usize>, ty: Option<String>, result: &str) -> usize {
+        let mut key = (op.to_string(), args.clone(), ty.clone());
+        if is_commutative(op) {
+            key.1.sort();
+        }
+        if let Some(node) = self.keys.get(&key) {
+            return *node;
+        }
+        let node = self.opaque(op, args, ty);
+        self.origin[node] = Some(result.to_string());
+        self.keys.insert(key, node);
+        node
+    }
+
+    // A node that is never shared, for values only known at runtime.
+    fn opaque(&mut self, op: &str, args: Vec<usize>, ty: Option<String>) -> usize {
+        self.nodes.push(DagNode { op: op.to_string(), args, ty, labels: Vec::new() });
+        self.origin.push(None);
+        self.fixed.push(false);
+        self.nodes.len() - 1
+    }
+
+    fn assign(&mut self, name: &str, node: usize) {
+        self.names.insert(name.to_string(), node);
+        if self.nodes[node].op != name {
+            self.nodes[node].labels.push(name.to_string());
+        }
+        self.order.retain(|i| i != name);
+        self.order.push(name.to_string());
+    }
+
+    // The names chosen by `f` assigned so far, with their values.
+    fn sync(&self, f: impl Fn(&str) -> bool) -> Vec<(String, usize)> {
+        self.order.iter().filter(|i| f(i)).map(|i| (i.clone(), self.names[i])).collect()
+    }
+
+    // Gives the names chosen by `f` a new leaf, for what they may hold now.
+    fn kill(&mut self, f: impl Fn(&str) -> bool, scope: &Scope) -> Vec<(String, usize)> {
+        let mut names: Vec<String> = self.names.keys().filter(|i| !is_literal(i) && f(i)).cloned().collect();
+        names.sort();
+        let mut kill = Vec::new();
+        for name in names {
+            let node = self.opaque(&name, Vec::new(), scope.ty(&name).map(|i| i.to_string()));
+            self.names.insert(name.clone(), node);
+            kill.push((name, node));
+        }
+        kill
+    }
+
+    fn generate(&mut self, scope: &Scope, temps: &mut usize) {
+        let mut starts = Vec::new();
+        for event in self.events.iter() {
+            starts.push(self.demands.len());
+            for (name, node) in event.sync.iter() {
+                self.stores.push((self.demands.len(), name.clone(), *node));
+                self.demands.push(*node);
+            }
+            self.demands.extend(event.args.iter().cloned());
+        }
+        for (mut event, start) in std::mem::take(&mut self.events).into_iter().zip(starts) {
+            self.at = start;
+            if let Some((name, node)) = event.write.as_mut().filter(|i| i.0.starts_with('@')) {
+                if let Some((target, value)) = self.write_target(*node, scope) {
+                    event.q.as_mut().unwrap().result = target.clone();
+                    *name = target;
+                    *node = value;
+                }
+            }
+            for (name, node) in event.sync.iter() {
+                self.store(name, *node, scope, temps);
+                self.at += 1;
+            }
+            let args = self.values(&event.args, scope, temps);
+            let written: Vec<&str> = event.write.iter().chain(event.kill.iter()).map(|i| i.0.as_str()).collect();
+            self.save(&written, None, scope, temps);
+            if let Some(mut q) = event.q {
+                for (arg, value) in operands_mut(&mut q).into_iter().zip(args) {
+                    *arg = value;
+                }
+                self.quaternions.push(q);
+            }
+            for (name, node) in event.write.into_iter().chain(event.kill) {
+                self.held.insert(name, node);
+            }
+        }
+    }
+
+    // Where a quaternion writing `node` into a temp writes it instead, with
+    // the node the name then holds: a name that wants it, or a variable it
+    // is converted into if nothing needs it as it is.
+    fn write_target(&self, node: usize, scope: &Scope) -> Option<(String, usize)> {
+        let mut candidates: Vec<(String, usize)> = self.targets(node, scope).into_iter().map(|i| (i, node)).collect();
+        for m in 0..self.nodes.len() {
+            let converted = self.fixed[m] && self.nodes[m].op == "=" && self.nodes[m].args == [node];
+            let origin = self.origin[m].clone().filter(|i| self.wants(i, m) && !scope.aliased(i));
+            if let Some(origin) = origin.filter(|_| converted && !self.need(&[], Some(m)).contains(&node)) {
+                candidates.push((origin, m));
+            }
+        }
+        candidates.sort_by_key(|i| i.0.starts_with('@'));
+        candidates.into_iter().find(|i| self.free(&i.0, &[], None))
+    }
+
+    // Makes `name` hold `node`.
+    fn store(&mut self, name: &str, node: usize, scope: &Scope, temps: &mut usize) {
+        if self.held.get(name) == Some(&node) {
+            return;
+        }
+        match self.holder(node) {
+            Some(value) => {
+                self.save(&[name], Some(node), scope, temps);
+                self.emit("=", vec![value], name);
+                self.held.insert(name.to_string(), node);
+            }
+            None => {
+                self.compute(node, Some(name), scope, temps);
+            }
+        }
+    }
+
+    // The name or literal to read `node`'s value from, computing it first
+    // if nothing holds it.
+    fn value(&mut self, node: usize, scope: &Scope, temps: &mut usize) -> String {
+        match self.holder(node) {
+            Some(value) => value,
+            None => self.compute(node, None, scope, temps),
+        }
+    }
+
+    // The same for each of `nodes`, once all are computed: computing one
+    // may have moved another out of the name it was in.
+    fn values(&mut self, nodes: &[usize], scope: &Scope, temps: &mut usize) -> Vec<String> {
+        for node in nodes.iter() {
+            self.value(*node, scope, temps);
+        }
+        nodes.iter().map(|i| self.holder(*i).expect("an operand is saved while it is needed")).collect()
+    }
+
+    // Computes `node` into `target`, or else into a name that holds nothing
+    // still needed, and returns that name.
+    fn compute(&mut self, node: usize, target: Option<&str>, scope: &Scope, temps: &mut usize) -> String {
+        let origin = self.origin[node].clone().expect("a value only known at runtime is never lost");
+        self.stack.push(node);
+        let args = self.values(&self.nodes[node].args.clone(), scope, temps);
+        self.stack.pop();
+        let target = match target {
+            Some(target) => target.to_string(),
+            None if self.fixed[node] => origin,
+            None => {
+                let candidates = self.targets(node, scope);
+                match candidates.into_iter().find(|i| self.free(i, &[], Some(node))) {
+                    Some(name) => name,
+                    None => fresh(temps),
+                }
+            }
+        };
+        self.save(&[&target], Some(node), scope, temps);
+        let op = self.nodes[node].op.clone();
+        self.emit(&op, args, &target);
+        self.held.insert(target.clone(), node);
+        target
+    }
+
+    // Copies out the values nothing but `names` holds and that are still
+    // needed, before `names` are overwritten with `computing`.
+    fn save(&mut self, names: &[&str], computing: Option<usize>, scope: &Scope, temps: &mut usize) {
+        for name in names.iter() {
+            let node = match self.held.get(*name) {
+                Some(node) => *node,
+                None => continue,
+            };
+            if !self.need(names, computing).contains(&node) {
+                continue;
+            }
+            // what can be computed from it is computed now instead, unless
+            // it is a division that may fail, or this is already saving
+            // for something computed that way
+            let top = self.busy.is_empty();
+            let busy = self.busy.len();
+            self.busy.extend(names.iter().map(|i| i.to_string()));
+            let mut using: Vec<usize> = self
+                .need(names, computing)
+                .into_iter()
+                .filter(|i| top && self.nodes[*i].args.contains(&node) && self.ready(*i, computing))
+                .filter(|i| !matches!(self.nodes[*i].op.as_str(), "/" | "%"))
+                .collect();
+            using.sort();
+            for i in using {
+                self.value(i, scope, temps);
+            }
+            self.busy.truncate(busy);
+            if !self.need(names, computing).contains(&node) {
+                continue;
+            }
+            let candidates = self.targets(node, scope).into_iter().filter(|i| !names.contains(&i.as_str()));
+            let temp = match candidates.into_iter().find(|i| self.free(i, names, computing)) {
+                Some(temp) => temp,
+                None => fresh(temps),
+            };
+            self.emit("=", vec![name.to_string()], &temp);
+            self.held.insert(temp, node);
+        }
+    }
+
+    // The names to compute `node` into, best first: a variable to be
+    // brought up to date with it, which then costs nothing, the name it was
+    // first computed into, and the temps given its value.
+    fn targets(&self, node: usize, scope: &Scope) -> Vec<String> {
+        let labels = &self.nodes[node].labels;
+        let last = labels.iter().filter(|i| !i.starts_with('@') && self.wants(i, node));
+        let temps_given = labels.iter().filter(|i| i.starts_with('@'));
+        let own = last.chain(self.origin[node].iter()).filter(|i| !scope.aliased(i));
+        own.chain(temps_given).cloned().collect()
+    }
+
+    // Whether `name` is next brought up to date with `node`.
+    fn wants(&self, name: &str, node: usize) -> bool {
+        let next = self.stores.iter().find(|i| i.0 >= self.at && i.1 == name);
+        next.is_some_and(|i| i.2 == node)
+    }
+
+    // Whether overwriting `name` loses nothing still needed.
+    fn free(&self, name: &str, exclude: &[&str], computing: Option<usize>) -> bool {
+        if self.busy.iter().any(|i| i == name) {
+            return false;
+        }
+        let mut exclude = exclude.to_vec();
+        exclude.push(name);
+        !self.held.get(name).is_some_and(|node| self.need(&exclude, computing).contains(node))
+    }
+
+    // The nodes still to be read, which no name but those in `exclude`
+    // holds; `computing` is about to be held.
+    fn need(&self, exclude: &[&str], computing: Option<usize>) -> HashSet<usize> {
+        let mut work: Vec<usize> = self.demands[self.at..].to_vec();
+        work.extend(self.stack.iter().flat_map(|i| self.nodes[*i].args.iter().cloned()));
+        let mut need = HashSet::new();
+        while let Some(node) = work.pop() {
+            let held = self.held.iter().any(|(name, i)| *i == node && !exclude.contains(&name.as_str()));
+            if Some(node) == computing || need.contains(&node) || self.is_constant(node) || held {
+                continue;
+            }
+            need.insert(node);
+            work.extend(self.nodes[node].args.iter().cloned());
+        }
+        need
+    }
+
+    fn holder(&self, node: usize) -> Option<String> {
+        if self.is_constant(node) {
+            return Some(self.nodes[node].op.clone());
+        }
+        let holds = |name: &String| self.held.get(name) == Some(&node);
+        let preferred = self.origin[node].iter().chain([&self.nodes[node].op]).find(|i| holds(i));
+        match preferred {
+            Some(name) => Some(name.clone()),
+            None => self.held.iter().find(|(_, i)| **i == node).map(|(name, _)| name.clone()),
+        }
+    }
+
+    // Whether `node` can be computed from what the names hold now, into a
+    // name not about to be overwritten, before `computing` is.
+    fn ready(&self, node: usize, computing: Option<usize>) -> bool {
+        let mut work = vec![node];
+        let mut seen = HashSet::new();
+        while let Some(node) = work.pop() {
+            if !seen.insert(node) || self.holder(node).is_some() {
+                continue;
+            }
+            let origin = match &self.origin[node] {
+                Some(origin) => origin,
+                None => return false,
+            };
+            if Some(node) == computing || (self.fixed[node] && self.busy.contains(origin)) {
+                return false;
+            }
+            work.extend(self.nodes[node].args.iter().cloned());
+        }
+        true
+    }
+
+    fn is_constant(&self, node: usize) -> bool {
+        self.nodes[node].args.is_empty() && is_literal(&self.nodes[node].op)
+    }
+
+    fn emit(&mut self, op: &str, args: Vec<String>, result: &str) {
+        let mut args = args.into_iter();
+        self.quaternions.push(Quaternion {
+            op: op.to_string(),
+            arg1: args.next().unwrap(),
+            arg2: args.next().unwrap_or_else(|| "_".to_string()),
+            result: result.to_string(),
+        });
+    }
+
+    fn type_of(&self, op: &str, args: &[usize]) -> Option<String> {
+        let ty = |i: usize| self.nodes[args[i]].ty.as_deref();
+        let ty = match op {
+            "+" | "-" | "*" | "/" | "%" => match (ty(0), ty(1)) {
+                (Some("integer"), Some("integer")) => Some("integer"),
+                (Some("integer" | "float"), Some("integer" | "float")) => Some("float"),
+                _ => None,
+            },
+            "neg" => ty(0),
+            _ => Some("boolean"),
+        };
+        ty.map(|i| i.to_string())
+    }
+}
+
+fn fresh(temps: &mut usize) -> String {
+    *temps += 1;
+    format!("@t{}", temps)
+}
+
+impl Display for Dag {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (id, node) in self.nodes.iter().enumerate() {
+            write!(f, "    n{}: ", id)?;
+            match node.args.is_empty() && node.op != "read" && node.op != "retval" {
+                true => write!(f, "{}", node.op)?,
+                false => {
+                    let args: Vec<String> = node.args.iter().map(|i| format!("n{}", i)).collect();
+                    write!(f, "({})", [vec![node.op.clone()], args].concat().join(", "))?
+                }
+            }
+            match node.labels.is_empty() {
+                true => writeln!(f)?,
+                false => writeln!(f, " -> {}", node.labels.join(", "))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// The DAG of each block of `cfg`, as `--emit dag` prints it.
+fn dags(cfg: &Cfg, scope: &Scope) -> String {
+    let live_out = live_temps(cfg);
+    let mut temps = last_temp(cfg);
+    let mut s = String::new();
+    for (id, live) in live_out.iter().enumerate().take(cfg.exit) {
+        let dag = Dag::new(&cfg.blocks[id].quaternions, live, scope, &mut temps);
+        s.push_str(&format!("B{}:\n{}", id, dag));
+    }
+    s
+}
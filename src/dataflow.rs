@@ -0,0 +1,327 @@
+// A data-flow analysis over a procedure's graph. Facts form a lattice in
+// which `meet` combines what flows in from several edges and `top` is its
+// identity; `transfer` carries a fact across one quaternion in the direction
+// of the analysis, `index` being the quaternion's place in the procedure.
+trait Analysis {
+    type Fact: Clone + PartialEq;
+
+    fn forward(&self) -> bool;
+    // what holds on entry to the procedure, or at its exit going backwards
+    fn boundary(&self) -> Self::Fact;
+    fn top(&self) -> Self::Fact;
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact);
+    fn transfer(&self, q: &Quaternion, index: usize, fact: &mut Self::Fact);
+    fn show(&self, fact: &Self::Fact) -> String;
+
+    fn transfer_block(&self, cfg: &Cfg, id: usize, start: usize, fact: &mut Self::Fact) {
+        let quads = &cfg.blocks[id].quaternions;
+        match self.forward() {
+            true => quads.iter().enumerate().for_each(|(i, q)| self.transfer(q, start + i, fact)),
+            false => quads.iter().enumerate().rev().for_each(|(i, q)| self.transfer(q, start + i, fact)),
+        }
+    }
+}
+
+// What holds before and after each block and each quaternion, in the order
+// the program runs whichever way the analysis went.
+struct Solution<F> {
+    pub blocks: Vec<(F, F)>,
+    pub quaternions: Vec<(F, F)>,
+}
+
+// Worklist iteration to the maximal fixed point.
+fn solve<A: Analysis>(cfg: &Cfg, analysis: &A) -> Solution<A::Fact> {
+    let n = cfg.blocks.len();
+    let mut start = Vec::new();
+    let mut count = 0;
+    for block in cfg.blocks.iter() {
+        start.push(count);
+        count += block.quaternions.len();
+    }
+    let forward = analysis.forward();
+
+    // `head` flows into a block, `tail` out of it
+    let mut head = vec![analysis.top(); n];
+    let mut tail = vec![analysis.top(); n];
+    let mut work: VecDeque<usize> = match forward {
+        true => cfg.reverse_postorder().into(),
+        false => (0..n).rev().collect(),
+    };
+    let mut queued = vec![false; n];
+    work.iter().for_each(|i| queued[*i] = true);
+    while let Some(id) = work.pop_front() {
+        queued[id] = false;
+        let (from, to) = match forward {
+            true => (&cfg.blocks[id].pred, &cfg.blocks[id].succ),
+            false => (&cfg.blocks[id].succ, &cfg.blocks[id].pred),
+        };
+        let mut fact = match forward && id == 0 || !forward && id == cfg.exit {
+            true => analysis.boundary(),
+            false => analysis.top(),
+        };
+        for i in from.iter() {
+            analysis.meet(&mut fact, &tail[*i]);
+        }
+        head[id] = fact.clone();
+        analysis.transfer_block(cfg, id, start[id], &mut fact);
+        if fact != tail[id] {
+            tail[id] = fact;
+            for &i in to.iter() {
+                if !queued[i] {
+                    queued[i] = true;
+                    work.push_back(i);
+                }
+            }
+        }
+    }
+
+    let mut quads_facts = Vec::new();
+    for id in 0..n {
+        let mut facts = Vec::new();
+        let mut fact = head[id].clone();
+        let quads = &cfg.blocks[id].quaternions;
+        let order: Vec<usize> = match forward {
+            true => (0..quads.len()).collect(),
+            false => (0..quads.len()).rev().collect(),
+        };
+        for i in order {
+            let before = fact.clone();
+            analysis.transfer(&quads[i], start[id] + i, &mut fact);
+            facts.push(match forward {
+                true => (before, fact.clone()),
+                false => (fact.clone(), before),
+            });
+        }
+        if !forward {
+            facts.reverse();
+        }
+        quads_facts.extend(facts);
+    }
+    let blocks = head
+        .into_iter()
+        .zip(tail)
+        .map(|(h, t)| match forward {
+            true => (h, t),
+            false => (t, h),
+        })
+        .collect();
+    Solution { blocks, quaternions: quads_facts }
+}
+
+// Every name a procedure reads or assigns.
+fn names_in(cfg: &Cfg) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for q in cfg.blocks.iter().flat_map(|b| b.quaternions.iter()) {
+        for name in operands(q).into_iter().map(|i| i.as_str()).chain(def(q)) {
+            if !is_literal(name) && !names.iter().any(|i| i == name) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names
+}
+
+// The names `q` may assign: a call may assign any variable, and assigning
+// one a `var` param may alias, or an element of an array it may be bound
+// to, any other it may alias.
+fn assigned<'a>(q: &'a Quaternion, names: &'a [String], scope: &Scope) -> Vec<&'a str> {
+    let mut assigned: Vec<&str> = Vec::new();
+    if q.op == "call" {
+        assigned.extend(names.iter().map(|i| i.as_str()).filter(|i| !i.starts_with('@')));
+    }
+    if let Some(name) = def(q) {
+        assigned.push(name);
+    }
+    if assigns_aliased(q, scope) {
+        assigned.extend(names.iter().map(|i| i.as_str()).filter(|i| Some(*i) != def(q) && scope.aliased(i)));
+    }
+    assigned
+}
+
+// Which assignments may reach a point unchanged, as `(name, index)`; an
+// index of `ENTRY` stands for the value a variable has on entry, temps
+// having none.
+struct ReachingDefinitions<'a> {
+    names: Vec<String>,
+    scope: &'a Scope,
+}
+
+const ENTRY: usize = usize::MAX;
+
+impl<'a> ReachingDefinitions<'a> {
+    fn new(cfg: &Cfg, scope: &'a Scope) -> Self {
+        ReachingDefinitions { names: names_in(cfg), scope }
+    }
+}
+
+impl<'a> Analysis for ReachingDefinitions<'a> {
+    type Fact = BTreeSet<(String, usize)>;
+
+    fn forward(&self) -> bool {
+        true
+    }
+
+    fn boundary(&self) -> Self::Fact {
+        self.names.iter().filter(|i| !i.starts_with('@')).map(|i| (i.clone(), ENTRY)).collect()
+    }
+
+    fn top(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.extend(other.iter().cloned());
+    }
+
+    // only a plain assignment is sure to replace what a name held
+    fn transfer(&self, q: &Quaternion, index: usize, fact: &mut Self::Fact) {
+        if let Some(name) = def(q) {
+            fact.retain(|i| i.0 != name);
+        }
+        for name in assigned(q, &self.names, self.scope) {
+            fact.insert((name.to_string(), index));
+        }
+    }
+
+    fn show(&self, fact: &Self::Fact) -> String {
+        let defs: Vec<String> = fact
+            .iter()
+            .map(|(name, i)| match *i {
+                ENTRY => format!("{}@entry", name),
+                i => format!("{}@{}", name, i),
+            })
+            .collect();
+        defs.join(", ")
+    }
+}
+
+// Which names may still be read before they are assigned. On exit only
+// what outlives the procedure is, a call may read any variable, and
+// loading from an aliased array any aliased name.
+struct LiveVariables<'a> {
+    names: Vec<String>,
+    scope: &'a Scope,
+}
+
+impl<'a> LiveVariables<'a> {
+    fn new(cfg: &Cfg, scope: &'a Scope) -> Self {
+        LiveVariables { names: names_in(cfg), scope }
+    }
+}
+
+impl<'a> Analysis for LiveVariables<'a> {
+    type Fact = BTreeSet<String>;
+
+    fn forward(&self) -> bool {
+        false
+    }
+
+    fn boundary(&self) -> Self::Fact {
+        self.names.iter().filter(|i| !i.starts_with('@') && !self.scope.local(i)).cloned().collect()
+    }
+
+    fn top(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.extend(other.iter().cloned());
+    }
+
+    fn transfer(&self, q: &Quaternion, _: usize, fact: &mut Self::Fact) {
+        if let Some(name) = def(q) {
+            fact.remove(name);
+        }
+        if q.op == "call" {
+            fact.extend(self.names.iter().filter(|i| !i.starts_with('@')).cloned());
+        }
+        if q.op == "=[]" && self.scope.aliased(&q.arg1) {
+            fact.extend(self.names.iter().filter(|i| self.scope.aliased(i)).cloned());
+        }
+        fact.extend(operands(q).into_iter().filter(|i| !is_literal(i)).cloned());
+    }
+
+    fn show(&self, fact: &Self::Fact) -> String {
+        fact.iter().cloned().collect::<Vec<_>>().join(", ")
+    }
+}
+
+// Which operations have been computed on every path, with none of their
+// operands assigned since, as `(op, arg1, arg2)`.
+struct AvailableExpressions<'a> {
+    names: Vec<String>,
+    scope: &'a Scope,
+    all: BTreeSet<(String, String, String)>,
+}
+
+impl<'a> AvailableExpressions<'a> {
+    fn new(cfg: &Cfg, scope: &'a Scope) -> Self {
+        let all = cfg.blocks.iter().flat_map(|b| b.quaternions.iter()).filter_map(expression).collect();
+        AvailableExpressions { names: names_in(cfg), scope, all }
+    }
+}
+
+fn expression(q: &Quaternion) -> Option<(String, String, String)> {
+    match def(q) {
+        Some(_) if is_pure(&q.op) && q.op != "=" => Some((q.op.clone(), q.arg1.clone(), q.arg2.clone())),
+        _ => None,
+    }
+}
+
+impl<'a> Analysis for AvailableExpressions<'a> {
+    type Fact = BTreeSet<(String, String, String)>;
+
+    fn forward(&self) -> bool {
+        true
+    }
+
+    fn boundary(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn top(&self) -> Self::Fact {
+        self.all.clone()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.retain(|i| other.contains(i));
+    }
+
+    fn transfer(&self, q: &Quaternion, _: usize, fact: &mut Self::Fact) {
+        if let Some(e) = expression(q) {
+            fact.insert(e);
+        }
+        for name in assigned(q, &self.names, self.scope) {
+            fact.retain(|(_, a, b)| a != name && b != name);
+        }
+    }
+
+    fn show(&self, fact: &Self::Fact) -> String {
+        let exprs: Vec<String> = fact
+            .iter()
+            .map(|(op, a, b)| match b.as_str() {
+                "_" => format!("{} {}", op, a),
+                _ => format!("{} {} {}", a, op, b),
+            })
+            .collect();
+        exprs.join(", ")
+    }
+}
+
+// What `--emit dataflow` prints: each quaternion, by its index, with the
+// definitions reaching it, the names live after it and the expressions
+// available before it.
+fn dataflow(cfg: &Cfg, scope: &Scope) -> String {
+    let reaching = ReachingDefinitions::new(cfg, scope);
+    let live = LiveVariables::new(cfg, scope);
+    let available = AvailableExpressions::new(cfg, scope);
+    let (r, l, a) = (solve(cfg, &reaching), solve(cfg, &live), solve(cfg, &available));
+    let mut s = String::new();
+    for (i, q) in cfg.linearize().iter().enumerate() {
+        s.push_str(&format!("{}: ({}, {}, {}, {})\n", i, q.op, q.arg1, q.arg2, q.result));
+        s.push_str(&format!("    reaching: {}\n", reaching.show(&r.quaternions[i].0)));
+        s.push_str(&format!("    live: {}\n", live.show(&l.quaternions[i].1)));
+        s.push_str(&format!("    available: {}\n", available.show(&a.quaternions[i].0)));
+    }
+    s
+}
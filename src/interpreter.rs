@@ -0,0 +1,452 @@
+#[derive(Clone, Copy, Debug)]
+enum Value {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+impl Value {
+    fn zero(ty: &str) -> Self {
+        match ty {
+            "float" => Value::Float(0.0),
+            "boolean" => Value::Boolean(false),
+            _ => Value::Integer(0),
+        }
+    }
+
+    // Converts `self` to the variant of `like`, so a slot keeps its declared type.
+    fn cast(self, like: Value) -> Self {
+        match (self, like) {
+            (Value::Float(v), Value::Integer(_)) => Value::Integer(v as i64),
+            (Value::Integer(v), Value::Float(_)) => Value::Float(v as f64),
+            _ => self,
+        }
+    }
+
+    fn as_float(self) -> f64 {
+        match self {
+            Value::Integer(v) => v as f64,
+            Value::Float(v) => v,
+            Value::Boolean(v) => v as i64 as f64,
+        }
+    }
+
+    fn is_true(self) -> bool {
+        match self {
+            Value::Boolean(v) => v,
+            _ => self.as_float() != 0.0,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Integer(v) => write!(f, "{}", v),
+            Value::Float(v) => write!(f, "{:?}", v),
+            Value::Boolean(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+// Activation record of one `call`: params and vars live in `memory` from
+// `base` on and are released when the procedure returns. Names map to
+// `(address, length)`, the length being above 1 only for arrays; a `var`
+// param maps to the slots of its argument in the caller. `link` is
+// the frame of the enclosing procedure, through which outer locals resolve.
+struct Frame {
+    proc: usize,
+    pc: usize,
+    base: usize,
+    link: Option<usize>,
+    names: HashMap<String, (usize, usize)>,
+    temps: HashMap<String, Value>,
+}
+
+struct Interpreter {
+    memory: Vec<Value>,
+    globals: HashMap<String, (usize, usize)>,
+    frames: Vec<Frame>,
+    args: Vec<Value>,
+    refs: Vec<(usize, usize)>,
+    retval: Option<Value>,
+    max_depth: usize,
+}
+
+unsafe fn interpret(max_depth: usize) -> Result<()> {
+    let mut vm = Interpreter {
+        memory: Vec::new(),
+        globals: HashMap::new(),
+        frames: Vec::new(),
+        args: Vec::new(),
+        refs: Vec::new(),
+        retval: None,
+        max_depth,
+    };
+    for i in global_vars.iter().filter(|i| is_stored(i)) {
+        let slot = vm.alloc(&i.ty);
+        vm.globals.insert(i.name.clone(), slot);
+    }
+
+    vm.call(entry)?;
+
+    while let Some(frame) = vm.frames.last() {
+        let (proc, pc) = (frame.proc, frame.pc);
+        let res = match proc_list[proc].quaternions.get(pc) {
+            Some(q) => vm.step(q),
+            None if proc_list[proc].ret.is_some() => Err(StandardError::new(
+                "reached the end of a function without `return`".to_string(),
+            )),
+            None => {
+                vm.ret(None);
+                Ok(())
+            }
+        };
+        if let Err(e) = res {
+            return Err(StandardError::new(format!(
+                "runtime error in `{}`, quaternion {}:\n{}",
+                proc_list[proc].name, pc, e.what
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+impl Interpreter {
+    unsafe fn step(&mut self, q: &Quaternion) -> Result<()> {
+        let frame = self.frames.last_mut().unwrap();
+        frame.pc += 1;
+        match q.op.as_str() {
+            "=" => {
+                let val = self.load(&q.arg1)?;
+                self.store(&q.result, val)?;
+            }
+            "=[]" => {
+                let addr = self.element(&q.arg1, &q.arg2)?;
+                self.store(&q.result, self.memory[addr])?;
+            }
+            "[]=" => {
+                let val = self.load(&q.arg1)?;
+                let addr = self.element(&q.result, &q.arg2)?;
+                self.memory[addr] = val.cast(self.memory[addr]);
+            }
+            "bound" => {
+                let len = q.arg2.parse().unwrap();
+                if let Err(e) = self.index(&q.arg1, len) {
+                    return Err(StandardError::new(format!("line {}: {}", q.result, e.what)));
+                }
+            }
+            "+" | "-" | "*" | "/" | "%" => {
+                let val = arith(&q.op, self.load(&q.arg1)?, self.load(&q.arg2)?)?;
+                self.store(&q.result, val)?;
+            }
+            "<" | "<=" | ">" | ">=" | "==" | "<>" => {
+                let val = compare(&q.op, self.load(&q.arg1)?, self.load(&q.arg2)?);
+                self.store(&q.result, Value::Boolean(val))?;
+            }
+            "and" | "or" => {
+                let (a, b) = (self.load(&q.arg1)?.is_true(), self.load(&q.arg2)?.is_true());
+                let val = if q.op == "and" { a && b } else { a || b };
+                self.store(&q.result, Value::Boolean(val))?;
+            }
+            "neg" => {
+                let val = match self.load(&q.arg1)? {
+                    Value::Integer(v) => Value::Integer(v.wrapping_neg()),
+                    Value::Float(v) => Value::Float(-v),
+                    val => val,
+                };
+                self.store(&q.result, val)?;
+            }
+            "not" => {
+                let val = !self.load(&q.arg1)?.is_true();
+                self.store(&q.result, Value::Boolean(val))?;
+            }
+            "j" => self.jump(&q.result),
+            "jnz" => {
+                if self.load(&q.arg1)?.is_true() {
+                    self.jump(&q.result);
+                }
+            }
+            "jez" => {
+                if !self.load(&q.arg1)?.is_true() {
+                    self.jump(&q.result);
+                }
+            }
+            // the entries follow the `jtab`, with the out-of-range jump after them
+            "jtab" => {
+                let (min, max): (i64, i64) = (q.arg2.parse().unwrap(), q.result.parse().unwrap());
+                let offset = match self.load(&q.arg1)? {
+                    Value::Integer(v) if v >= min && v <= max => v.abs_diff(min),
+                    _ => max.abs_diff(min) + 1,
+                };
+                self.frames.last_mut().unwrap().pc += offset as usize;
+            }
+            "read" => {
+                let mut line = String::new();
+                if std::io::stdin().read_line(&mut line).is_err() {
+                    return Err(StandardError::new("failed to read input".to_string()));
+                }
+                let val = match self.load(&q.result)? {
+                    Value::Integer(_) => line.trim().parse().map(Value::Integer).ok(),
+                    Value::Float(_) => line.trim().parse().map(Value::Float).ok(),
+                    Value::Boolean(_) => line.trim().parse().map(Value::Boolean).ok(),
+                };
+                match val {
+                    Some(val) => self.store(&q.result, val)?,
+                    None => {
+                        return Err(StandardError::new(format!(
+                            "`{}` is not a valid value for `{}`",
+                            line.trim(),
+                            q.result
+                        )))
+                    }
+                }
+            }
+            "write" => println!("{}", self.load(&q.arg1)?),
+            "par" => match self.address(&q.arg1) {
+                Some((addr, len)) => {
+                    let vals = self.memory[addr..addr + len].to_vec();
+                    self.args.extend(vals);
+                }
+                None => {
+                    let val = self.load(&q.arg1)?;
+                    self.args.push(val);
+                }
+            },
+            "ref" => {
+                let slot = match q.arg2.as_str() {
+                    "_" => self.address(&q.arg1).unwrap(),
+                    offset => (self.element(&q.arg1, offset)?, 1),
+                };
+                self.refs.push(slot);
+            }
+            "call" => {
+                let proc = callee(self.frames.last().unwrap().proc, &q.arg1).unwrap();
+                self.call(proc)?;
+            }
+            "ret" => {
+                let val = match q.arg1.as_str() {
+                    "_" => None,
+                    arg1 => Some(self.load(arg1)?),
+                };
+                self.ret(val);
+            }
+            "retval" => {
+                let val = self.retval.take().unwrap();
+                self.store(&q.result, val)?;
+            }
+            _ => {
+                return Err(StandardError::new(format!(
+                    "unknown operator `{}`",
+                    q.op
+                )))
+            }
+        }
+
+        Ok(())
+    }
+
+    unsafe fn call(&mut self, proc: usize) -> Result<()> {
+        let callee = &proc_list[proc];
+        if self.frames.len() == self.max_depth {
+            return Err(StandardError::new(format!(
+                "call to `{}` exceeds the maximum recursion depth of {}",
+                callee.name, self.max_depth
+            )));
+        }
+
+        // the innermost activation of the parent along the caller's static chain
+        let mut link = self.frames.len().checked_sub(1);
+        if let Some(parent) = callee.parent {
+            while let Some(i) = link {
+                if self.frames[i].proc == parent {
+                    break;
+                }
+                link = self.frames[i].link;
+            }
+        } else {
+            link = None;
+        }
+
+        let mut frame = Frame {
+            proc,
+            pc: 0,
+            base: self.memory.len(),
+            link,
+            names: HashMap::new(),
+            temps: HashMap::new(),
+        };
+        let nrefs = callee.params.iter().filter(|i| i.kind == Kind::Ref).count();
+        let mut refs = self.refs.split_off(self.refs.len() - nrefs).into_iter();
+        for i in callee.params.iter() {
+            let slot = match i.kind {
+                Kind::Ref => refs.next().unwrap(),
+                _ => self.alloc(&i.ty),
+            };
+            frame.names.insert(i.name.clone(), slot);
+        }
+        let args = self.args.split_off(self.args.len() - (self.memory.len() - frame.base));
+        for (addr, arg) in (frame.base..self.memory.len()).zip(args) {
+            self.memory[addr] = arg.cast(self.memory[addr]);
+        }
+        for i in callee.vars.iter().filter(|i| is_stored(i)) {
+            let slot = self.alloc(&i.ty);
+            frame.names.insert(i.name.clone(), slot);
+        }
+        self.frames.push(frame);
+
+        Ok(())
+    }
+
+    // Pops the activation record; a function's result is cast to its declared type.
+    unsafe fn ret(&mut self, val: Option<Value>) {
+        let frame = self.frames.pop().unwrap();
+        self.memory.truncate(frame.base);
+        if let (Some(val), Some(ty)) = (val, &proc_list[frame.proc].ret) {
+            self.retval = Some(val.cast(Value::zero(ty)));
+        }
+    }
+
+    fn jump(&mut self, target: &str) {
+        self.frames.last_mut().unwrap().pc = target.parse().unwrap();
+    }
+
+    unsafe fn alloc(&mut self, ty: &str) -> (usize, usize) {
+        let addr = self.memory.len();
+        self.layout(ty);
+        (addr, self.memory.len() - addr)
+    }
+
+    // Pushes the zero value of every slot of `ty`, keeping each slot's scalar type.
+    unsafe fn layout(&mut self, ty: &str) {
+        if let Some((len, elem)) = array_type(ty) {
+            for _ in 0..len {
+                self.layout(elem);
+            }
+        } else if let Some(record) = records.iter().find(|record| record.0 == ty) {
+            for i in record.1.iter() {
+                self.layout(i.ty.as_str());
+            }
+        } else {
+            self.memory.push(Value::zero(ty));
+        }
+    }
+
+    fn index(&self, name: &str, len: usize) -> Result<usize> {
+        match self.load(name)? {
+            Value::Integer(i) if 0 <= i && (i as usize) < len => Ok(i as usize),
+            Value::Integer(i) => Err(StandardError::new(format!(
+                "index {} is out of bounds for length {}",
+                i, len
+            ))),
+            _ => Err(StandardError::new(
+                "array index must be an integer".to_string(),
+            )),
+        }
+    }
+
+    // Address of element `index` of array `name`.
+    fn element(&self, name: &str, index: &str) -> Result<usize> {
+        let (addr, len) = self.address(name).unwrap();
+        Ok(addr + self.index(index, len)?)
+    }
+
+    fn address(&self, name: &str) -> Option<(usize, usize)> {
+        let mut frame = self.frames.last();
+        while let Some(f) = frame {
+            if let Some(slot) = f.names.get(name) {
+                return Some(*slot);
+            }
+            frame = f.link.map(|i| &self.frames[i]);
+        }
+        self.globals.get(name).copied()
+    }
+
+    fn load(&self, name: &str) -> Result<Value> {
+        if is_literal(name) {
+            return match literal(name) {
+                Some(val) => Ok(val),
+                None => Err(StandardError::new(format!("`{}` is not a number", name))),
+            };
+        }
+        if name.starts_with('@') {
+            return match self.frames.last().unwrap().temps.get(name) {
+                Some(val) => Ok(*val),
+                None => Err(StandardError::new(format!("`{}` is used before assignment", name))),
+            };
+        }
+        match self.address(name) {
+            Some((addr, _)) => Ok(self.memory[addr]),
+            None => Err(StandardError::new(format!("`{}` is not bound", name))),
+        }
+    }
+
+    fn store(&mut self, name: &str, val: Value) -> Result<()> {
+        if name.starts_with('@') {
+            self.frames.last_mut().unwrap().temps.insert(name.to_string(), val);
+            return Ok(());
+        }
+        match self.address(name) {
+            Some((addr, _)) => {
+                self.memory[addr] = val.cast(self.memory[addr]);
+                Ok(())
+            }
+            None => Err(StandardError::new(format!("`{}` is not bound", name))),
+        }
+    }
+}
+
+fn literal(val: &str) -> Option<Value> {
+    if val == "true" || val == "false" {
+        return Some(Value::Boolean(val == "true"));
+    }
+    match (val.parse(), val.parse()) {
+        (Ok(v), _) => Some(Value::Integer(v)),
+        (_, Ok(v)) => Some(Value::Float(v)),
+        _ => None,
+    }
+}
+
+// Constants are folded away and procedures and record types take no memory.
+fn is_stored(var: &Var) -> bool {
+    var.kind == Kind::Var && !["procedure", "function", "record"].contains(&var.ty.as_str())
+}
+
+fn arith(op: &str, a: Value, b: Value) -> Result<Value> {
+    if let (Value::Integer(a), Value::Integer(b)) = (a, b) {
+        return match op {
+            "+" => Ok(Value::Integer(a.wrapping_add(b))),
+            "-" => Ok(Value::Integer(a.wrapping_sub(b))),
+            "*" => Ok(Value::Integer(a.wrapping_mul(b))),
+            _ if b == 0 => Err(StandardError::new("division by zero".to_string())),
+            "%" => Ok(Value::Integer(a.wrapping_rem(b))),
+            _ => Ok(Value::Integer(a.wrapping_div(b))),
+        };
+    }
+
+    let (a, b) = (a.as_float(), b.as_float());
+    match op {
+        "+" => Ok(Value::Float(a + b)),
+        "-" => Ok(Value::Float(a - b)),
+        "*" => Ok(Value::Float(a * b)),
+        _ if b == 0.0 => Err(StandardError::new("division by zero".to_string())),
+        _ => Ok(Value::Float(a / b)),
+    }
+}
+
+fn compare(op: &str, a: Value, b: Value) -> bool {
+    let ord = match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => a.partial_cmp(&b),
+        (Value::Boolean(a), Value::Boolean(b)) => a.partial_cmp(&b),
+        _ => a.as_float().partial_cmp(&b.as_float()),
+    };
+    match op {
+        "<" => ord == Some(std::cmp::Ordering::Less),
+        "<=" => ord.is_some_and(|o| o != std::cmp::Ordering::Greater),
+        ">" => ord == Some(std::cmp::Ordering::Greater),
+        ">=" => ord.is_some_and(|o| o != std::cmp::Ordering::Less),
+        "==" => ord == Some(std::cmp::Ordering::Equal),
+        _ => ord != Some(std::cmp::Ordering::Equal),
+    }
+}
@@ -6,12 +6,22 @@ use std::{
 pub fn lexical_analysis(path: &str) -> Vec<Word> {
     let s = fs::read_to_string(path).unwrap();
     let s = s.as_bytes();
-    const KEYWORDS: [&str; 18] = [
+    const KEYWORDS: [&str; 40] = [
         "program",
+        "type",
+        "record",
+        "const",
         "var",
         "integer",
         "float",
+        "boolean",
+        "true",
+        "false",
+        "array",
+        "of",
         "procedure",
+        "function",
+        "return",
         "begin",
         "end",
         "read",
@@ -19,12 +29,24 @@ pub fn lexical_analysis(path: &str) -> Vec<Word> {
         "if",
         "then",
         "else",
+        "elsif",
         "fi",
+        "case",
+        "esac",
         "while",
         "do",
         "endwh",
+        "for",
+        "to",
+        "step",
+        "endfor",
+        "repeat",
+        "until",
+        "break",
+        "continue",
         "and",
         "or",
+        "not",
     ];
 
     let mut r = 1usize;
@@ -242,11 +264,13 @@ fn is_number_u8(c: u8) -> bool {
 }
 
 fn is_separator_u8(c: u8) -> bool {
-    const SEPARATOR: [u8; 6] = [b'(', b')', b'{', b'}', b';', b','];
+    const SEPARATOR: [u8; 10] = [
+        b'(', b')', b'{', b'}', b'[', b']', b';', b',', b':', b'.',
+    ];
     SEPARATOR.contains(&c)
 }
 
 fn is_operator_u8(c: u8) -> bool {
-    const OPERATOR: [u8; 7] = [b'+', b'-', b'*', b'/', b'=', b'<', b'>'];
+    const OPERATOR: [u8; 8] = [b'+', b'-', b'*', b'/', b'%', b'=', b'<', b'>'];
     OPERATOR.contains(&c)
 }
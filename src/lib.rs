@@ -0,0 +1,5 @@
+// The control-flow graph the compiler builds over a procedure's
+// quaternions. The compiler uses it from here too, so other tools get the
+// same graph it optimizes.
+pub mod cfg;
+pub mod quaternion;
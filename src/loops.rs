@@ -0,0 +1,211 @@
+// `-O2`: moves the quaternions of each natural loop that compute the same
+// value on every iteration into a preheader, and replaces multiplying an
+// induction variable by a constant with an addition kept in step with it.
+fn optimize_loops(cfg: &mut Cfg, scope: &Scope) {
+    let mut temps = last_temp(cfg);
+    loop {
+        let dom = cfg.dominators();
+        let natural = cfg.loops(&dom);
+        if !natural.iter().any(|l| optimize_loop(cfg, scope, &dom, l, &mut temps)) {
+            break;
+        }
+    }
+}
+
+// The highest `@tN` the procedure uses, so that new temps come after it.
+fn last_temp(cfg: &Cfg) -> usize {
+    let mut last = 0;
+    for q in cfg.blocks.iter().flat_map(|b| b.quaternions.iter()) {
+        for name in [&q.arg1, &q.arg2, &q.result] {
+            if let Some(Ok(n)) = name.strip_prefix("@t").map(|n| n.parse::<usize>()) {
+                last = last.max(n);
+            }
+        }
+    }
+    last
+}
+
+// Side-effect free operations whose value only depends on their operands.
+fn is_pure(op: &str) -> bool {
+    matches!(
+        op,
+        "=" | "+" | "-" | "*" | "/" | "%" | "<" | "<=" | ">" | ">=" | "==" | "<>" | "and" | "or" | "not" | "neg"
+    )
+}
+
+// Returns whether anything was changed, in which case the graph has been
+// rebuilt and `dom` no longer matches it.
+fn optimize_loop(cfg: &mut Cfg, scope: &Scope, dom: &Dominators, l: &Loop, temps: &mut usize) -> bool {
+    let header = l.header;
+    // the preheader goes right before the header, so a block of the loop
+    // must not fall through into it
+    if cfg.jump_table_entries().contains(&header)
+        || header > 0
+            && l.blocks.contains(&(header - 1))
+            && !cfg.blocks[header - 1].quaternions.last().is_some_and(|q| q.op == "j" || q.op == "ret")
+    {
+        return false;
+    }
+
+    let quads: Vec<(usize, usize, &Quaternion)> = l
+        .blocks
+        .iter()
+        .flat_map(|&b| cfg.blocks[b].quaternions.iter().enumerate().map(move |(i, q)| (b, i, q)))
+        .collect();
+    let mut defs: HashMap<&str, usize> = HashMap::new();
+    for (_, _, q) in quads.iter() {
+        if let Some(name) = def(q) {
+            *defs.entry(name).or_insert(0) += 1;
+        }
+    }
+    let has_call = quads.iter().any(|(_, _, q)| q.op == "call");
+    let aliased_def = quads.iter().any(|(_, _, q)| assigns_aliased(q, scope));
+    let exits: Vec<usize> =
+        l.blocks.iter().cloned().filter(|b| cfg.blocks[*b].succ.iter().any(|s| !l.blocks.contains(s))).collect();
+    let read_outside = |name: &str| {
+        cfg.blocks
+            .iter()
+            .enumerate()
+            .filter(|(b, _)| !l.blocks.contains(b))
+            .any(|(_, block)| block.quaternions.iter().any(|q| operands(q).iter().any(|i| *i == name)))
+    };
+    // a name keeps its value throughout the loop
+    let unchanged = |name: &str| {
+        // a call may assign any variable, a `var` param any it aliases
+        let touched = has_call && !name.starts_with('@') || aliased_def && scope.aliased(name);
+        is_literal(name) || !defs.contains_key(name) && !touched
+    };
+
+    // invariant quaternions, each after those computing its operands
+    let mut hoisted: Vec<(usize, usize)> = Vec::new();
+    let mut invariant: Vec<&str> = Vec::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &(b, i, q) in quads.iter() {
+            if !is_pure(&q.op)
+                || hoisted.contains(&(b, i))
+                || !operands(q).iter().all(|arg| unchanged(arg) || invariant.contains(&arg.as_str()))
+            {
+                continue;
+            }
+            let name = q.result.as_str();
+            let safe = !(q.op == "/" || q.op == "%") || unchanged(&q.arg2) && literal(&q.arg2).is_some_and(|v| v.is_true());
+            let always = !exits.is_empty() && exits.iter().all(|e| dom.dominates(b, *e));
+            let temp = name.starts_with('@') && !read_outside(name);
+            if defs[name] != 1
+                || scope.aliased(name)
+                || !name.starts_with('@') && has_call
+                || !(always || temp && safe)
+            {
+                continue;
+            }
+            // every read of it in the loop must see this definition
+            let dominated = quads.iter().all(|&(b2, i2, q2)| {
+                !operands(q2).iter().any(|arg| *arg == name) || (b2 == b && i2 > i) || (b2 != b && dom.dominates(b, b2))
+            });
+            if dominated {
+                hoisted.push((b, i));
+                invariant.push(name);
+                changed = true;
+            }
+        }
+    }
+
+    // basic induction variables: `(+, i, c, i)` or `(-, i, c, i)` is the
+    // only definition of `i` in the loop
+    let mut steps: HashMap<&str, (usize, usize, &str, i64)> = HashMap::new();
+    for &(b, i, q) in quads.iter() {
+        let step = match (q.op.as_str(), q.arg1 == q.result, q.arg2 == q.result) {
+            ("+" | "-", true, false) => &q.arg2,
+            ("+", false, true) => &q.arg1,
+            _ => continue,
+        };
+        let name = q.result.as_str();
+        if let (Some(Value::Integer(c)), true) = (literal(step), is_literal(step)) {
+            if defs[name] == 1 && scope.ty(name) == Some("integer") && !scope.aliased(name) && !has_call {
+                steps.insert(name, (b, i, if q.arg1 == q.result { q.op.as_str() } else { "+" }, c));
+            }
+        }
+    }
+    // `(*, i, k, t)` becomes `(=, s, _, t)`, `s` starting as `i * k` and
+    // moving by `c * k` whenever `i` moves by `c`
+    let mut reduced: Vec<(usize, usize, String)> = Vec::new();
+    let mut updates: Vec<(usize, usize, Quaternion)> = Vec::new();
+    let mut preheader: Vec<Quaternion> = Vec::new();
+    let mut derived: HashMap<(&str, i64), String> = HashMap::new();
+    for &(b, i, q) in quads.iter() {
+        let (iv, k) = match (q.op.as_str(), steps.contains_key(q.arg1.as_str()), steps.contains_key(q.arg2.as_str())) {
+            ("*", true, false) => (q.arg1.as_str(), &q.arg2),
+            ("*", false, true) => (q.arg2.as_str(), &q.arg1),
+            _ => continue,
+        };
+        let k = match (literal(k), is_literal(k)) {
+            (Some(Value::Integer(k)), true) => k,
+            _ => continue,
+        };
+        let s = derived.entry((iv, k)).or_insert_with(|| {
+            *temps += 1;
+            let s = format!("@t{}", temps);
+            let (sb, si, op, c) = steps[iv];
+            preheader.push(Quaternion {
+                op: "*".to_string(),
+                arg1: iv.to_string(),
+                arg2: k.to_string(),
+                result: s.clone(),
+            });
+            updates.push((
+                sb,
+                si,
+                Quaternion {
+                    op: op.to_string(),
+                    arg1: s.clone(),
+                    arg2: c.wrapping_mul(k).to_string(),
+                    result: s.clone(),
+                },
+            ));
+            s
+        });
+        reduced.push((b, i, s.clone()));
+    }
+
+    if hoisted.is_empty() && reduced.is_empty() {
+        return false;
+    }
+    let mut moved: Vec<Quaternion> = hoisted.iter().map(|&(b, i)| cfg.blocks[b].quaternions[i].clone()).collect();
+    moved.extend(preheader);
+    for &b in l.blocks.iter() {
+        let mut quads = Vec::new();
+        for (i, q) in cfg.blocks[b].quaternions.iter().enumerate() {
+            if hoisted.contains(&(b, i)) {
+                continue;
+            }
+            match reduced.iter().find(|r| r.0 == b && r.1 == i) {
+                Some((_, _, s)) => quads.push(Quaternion {
+                    op: "=".to_string(),
+                    arg1: s.clone(),
+                    arg2: "_".to_string(),
+                    result: q.result.clone(),
+                }),
+                None => quads.push(q.clone()),
+            }
+            quads.extend(updates.iter().filter(|u| u.0 == b && u.1 == i).map(|u| u.2.clone()));
+        }
+        cfg.blocks[b].quaternions = quads;
+    }
+
+    // blocks outside the loop that jumped to the header enter through the
+    // preheader; the one laid out before it falls through into it
+    for (id, block) in cfg.blocks.iter_mut().enumerate() {
+        for q in block.quaternions.iter_mut().filter(|q| is_jump(&q.op)) {
+            let target: usize = q.result.parse().unwrap();
+            if target > header || target == header && l.blocks.contains(&id) {
+                q.result = (target + 1).to_string();
+            }
+        }
+    }
+    cfg.blocks.insert(header, BasicBlock { quaternions: moved, ..Default::default() });
+    cfg.exit += 1;
+    cfg.rebuild();
+    true
+}
@@ -1,5 +1,22 @@
 include!("lexical_analysis.rs");
+include!("interpreter.rs");
+include!("optimize.rs");
+include!("dag.rs");
+include!("loops.rs");
+include!("ssa.rs");
+include!("dataflow.rs");
+include!("warnings.rs");
+use syntax_directed_translation::cfg::is_jump;
+use syntax_directed_translation::cfg::BasicBlock;
+use syntax_directed_translation::cfg::Cfg;
+use syntax_directed_translation::cfg::Dominators;
+use syntax_directed_translation::cfg::Loop;
+use syntax_directed_translation::quaternion::Quaternion;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::error::Error;
 use std::fmt::Display;
 use std::mem::swap;
@@ -28,34 +45,86 @@ impl StandardError {
     }
 }
 
-#[derive(Clone, Debug)]
-struct Quaternion {
-    pub op: String,
-    pub arg1: String,
-    pub arg2: String,
-    pub result: String,
+#[derive(Clone, Debug, PartialEq)]
+enum Kind {
+    Var,
+    // a named constant and the literal it is folded to
+    Const(String),
+    // a `var` param, bound to the caller's variable
+    Ref,
 }
 
 #[derive(Clone, Debug)]
 struct Var {
     pub name: String,
     pub ty: String,
+    pub kind: Kind,
 }
 
 impl Var {
     pub unsafe fn new(name: String, ty: String) -> Result<Self> {
-        for i in params.iter().chain(vars.iter()).chain(global_vars.iter()) {
-            if i.name == name {
-                return Err(StandardError::new(format!("`{}` has been defined", name)));
-            }
+        if symbol(name.as_str()).is_some() {
+            return Err(StandardError::new(format!("`{}` has been defined", name)));
+        }
+        Ok(Var {
+            name,
+            ty,
+            kind: Kind::Var,
+        })
+    }
+}
+
+impl Display for Var {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Name: {}, Type: {}", self.name, self.ty)?;
+        match &self.kind {
+            Kind::Const(val) => write!(f, ", Value: {}", val),
+            Kind::Ref => write!(f, ", By: reference"),
+            Kind::Var => Ok(()),
         }
-        Ok(Var { name, ty })
     }
 }
 
+// Splits an array type `array[N] of T` into `(N, T)`.
+fn array_type(ty: &str) -> Option<(usize, &str)> {
+    let (len, elem) = ty.strip_prefix("array[")?.split_once("] of ")?;
+    Some((len.parse().ok()?, elem))
+}
+
+const NUMERIC: [&str; 2] = ["integer", "float"];
+const SCALAR: [&str; 3] = ["integer", "float", "boolean"];
+
+fn is_scalar(ty: &str) -> bool {
+    SCALAR.contains(&ty)
+}
+
+// Number of value slots a variable of type `ty` occupies.
+unsafe fn size_of(ty: &str) -> usize {
+    if let Some((len, elem)) = array_type(ty) {
+        return len * size_of(elem);
+    }
+    match records.iter().find(|record| record.0 == ty) {
+        Some(record) => record.1.iter().map(|i| size_of(i.ty.as_str())).sum(),
+        None => 1,
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Proc {
+    pub name: String,
+    pub quaternions: Vec<Quaternion>,
+    pub params: Vec<Var>,
+    pub vars: Vec<Var>,
+    // `None` for procedures, the result type for functions.
+    pub ret: Option<String>,
+    // the procedure this one is declared in, `None` at program level
+    pub parent: Option<usize>,
+}
+
 #[derive(Default)]
 struct GenTemp {
     n: usize,
+    ty: Vec<String>,
 }
 
 impl GenTemp {
@@ -65,12 +134,22 @@ impl GenTemp {
     fn contains(&self, s: &str) -> bool {
         s.as_bytes()[0] == b'@'
     }
-    fn gen(&mut self) -> String {
+    fn gen(&mut self, ty: &str) -> String {
         self.n += 1;
+        self.ty.push(ty.to_string());
         format!("@t{}", self.n)
     }
+    fn type_of(&self, s: &str) -> String {
+        self.ty[s[2..].parse::<usize>().unwrap() - 1].clone()
+    }
     fn reset(&mut self) {
         self.n = 0;
+        self.ty.clear();
+    }
+    // forgets the temps generated after the first `n`
+    fn rewind(&mut self, n: usize) {
+        self.n = n;
+        self.ty.truncate(n);
     }
 }
 
@@ -79,45 +158,164 @@ static mut words: Vec<Word> = Vec::new();
 static mut global_vars: Vec<Var> = Vec::new();
 static mut vars: Vec<Var> = Vec::new();
 static mut params: Vec<Var> = Vec::new();
-static mut temp_gen: GenTemp = GenTemp { n: 0 };
+static mut temp_gen: GenTemp = GenTemp {
+    n: 0,
+    ty: Vec::new(),
+};
 static mut quaternions: Vec<Quaternion> = Vec::new();
-static mut proc_list: Vec<(String, Vec<Quaternion>, Vec<Var>, Vec<Var>)> = Vec::new();
+static mut proc_list: Vec<Proc> = Vec::new();
+static mut records: Vec<(String, Vec<Var>)> = Vec::new();
+static mut current: usize = 0;
+// procedures enclosing the one being declared; their params and vars are
+// parked in `proc_list` while the inner one is parsed
+static mut scopes: Vec<usize> = Vec::new();
+// the procedure execution starts from, set once the whole program is parsed
+static mut entry: usize = 0;
+// pending `break` and `continue` jumps of each enclosing loop
+static mut loops: Vec<(Vec<usize>, Vec<usize>)> = Vec::new();
+// forms `--emit` can print instead of the symbol tables and quaternions
+const EMIT: [&str; 5] = ["cfg", "cfg-dot", "dag", "ssa", "dataflow"];
+// warnings `--allow` can turn off
+const ALLOW: [&str; 2] = ["uninitialized", "unused"];
+
 fn main() {
+    let mut path = "resource/e5.txt".to_string();
+    let mut run = false;
+    let mut max_depth = 1000;
+    let mut emit = Vec::new();
+    let mut level = 0;
+    let mut allow = Vec::new();
+    let mut ssa = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--run" => run = true,
+            "--max-depth" => match args.next().map(|n| n.parse()) {
+                Some(Ok(n)) if n > 0 => max_depth = n,
+                _ => {
+                    println!("`--max-depth` expects a positive integer");
+                    return;
+                }
+            },
+            "--emit" => match args.next() {
+                Some(form) if EMIT.contains(&form.as_str()) => emit.push(form),
+                _ => {
+                    println!("`--emit` expects one of: {}", EMIT.join(", "));
+                    return;
+                }
+            },
+            "--allow" => match args.next() {
+                Some(warning) if ALLOW.contains(&warning.as_str()) => allow.push(warning),
+                _ => {
+                    println!("`--allow` expects one of: {}", ALLOW.join(", "));
+                    return;
+                }
+            },
+            "--ssa" => ssa = true,
+            "-O0" => level = 0,
+            "-O1" => level = 1,
+            "-O2" => level = 2,
+            _ => path = arg,
+        }
+    }
+
     unsafe {
-        words = lexical_analysis("resource/e5.txt");
-        syntax_analysis();
+        words = lexical_analysis(path.as_str());
+        let ok = syntax_analysis();
+        let mut warned = Vec::new();
+        if ok {
+            warned = warnings(&allow);
+        }
+        let mut report = Vec::new();
+        if ok && (level > 0 || ssa) {
+            report = optimize(level, ssa);
+        }
+        if emit.is_empty() {
+            for line in warned.iter() {
+                println!("{}", line);
+            }
+            dump(ok);
+            for line in report.iter() {
+                println!("{}", line);
+            }
+        }
+        if !ok {
+            return;
+        }
+        for form in emit.iter() {
+            for (i, proc) in proc_list.iter().enumerate() {
+                let cfg = Cfg::new(&proc.quaternions);
+                match form.as_str() {
+                    "cfg" => print!("cfg {}:\n{}", proc.name, cfg),
+                    "dag" => print!("dag {}:\n{}", proc.name, dags(&cfg, &Scope::new(i))),
+                    "ssa" => print!("ssa {}:\n{}", proc.name, Ssa::new(&cfg, &Scope::new(i))),
+                    "dataflow" => print!("dataflow {}:\n{}", proc.name, dataflow(&cfg, &Scope::new(i))),
+                    _ => print!("{}", cfg.dot(&proc.name)),
+                }
+            }
+        }
+        if run {
+            if let Err(e) = interpret(max_depth) {
+                println!("{}", e)
+            }
+        }
     }
 }
 
-pub unsafe fn syntax_analysis() {
-    if let Err(e) = program() {
+pub unsafe fn syntax_analysis() -> bool {
+    let res = program();
+    if let Err(e) = &res {
         println!("{}", e)
     };
+
+    res.is_ok()
+}
+
+// Prints the symbol tables and each procedure's quaternions; the entry point
+// is only known once the whole program has been parsed.
+unsafe fn dump(ok: bool) {
+    for record in records.iter() {
+        println!("record {}:", record.0);
+        let mut offset = 0;
+        for i in record.1.iter() {
+            println!("Name: {}, Type: {}, Offset: {}", i.name, i.ty, offset);
+            offset += size_of(i.ty.as_str());
+        }
+    }
     if !global_vars.is_empty() {
         println!("global:");
         for i in global_vars.iter() {
-            println!("Name: {}, Type: {}", i.name, i.ty);
+            println!("{}", i);
         }
     }
 
     for proc in proc_list.iter() {
-        println!("procedure {}:", proc.0);
-        if !proc.2.is_empty() {
+        let mut name = proc.name.clone();
+        let mut parent = proc.parent;
+        while let Some(i) = parent {
+            name = format!("{}.{}", proc_list[i].name, name);
+            parent = proc_list[i].parent;
+        }
+        match &proc.ret {
+            None => println!("procedure {}:", name),
+            Some(ty) => println!("function {}: {}", name, ty),
+        }
+        if !proc.params.is_empty() {
             println!("params:");
-            for i in proc.2.iter() {
-                println!("Name: {}, Type: {}", i.name, i.ty);
+            for i in proc.params.iter() {
+                println!("{}", i);
             }
         }
-        if !proc.3.is_empty() {
+        if !proc.vars.is_empty() {
             println!("vars:");
-            for i in proc.3.iter() {
-                println!("Name: {}, Type: {}", i.name, i.ty);
+            for i in proc.vars.iter() {
+                println!("{}", i);
             }
         }
-        if !proc.1.is_empty() {
+        if !proc.quaternions.is_empty() {
             println!("quaternions:");
-            for index in 0..proc.1.len() {
-                let i = proc.1.get_unchecked(index);
+            for index in 0..proc.quaternions.len() {
+                let i = proc.quaternions.get_unchecked(index);
                 println!(
                     "{}: ({}, {}, {}, {})",
                     index, i.op, i.arg1, i.arg2, i.result
@@ -125,12 +323,18 @@ pub unsafe fn syntax_analysis() {
             }
         }
     }
+    if ok {
+        println!("entry: {}", proc_list[entry].name);
+    }
 }
 
 unsafe fn program() -> Result<String> {
-    program_head()?;
+    let name = program_head()?;
+    type_decpart()?;
+    const_decpart()?;
     var_decpart()?;
     program_body()?;
+    let block = main_block(name)?;
     if idx != words.len() {
         return Err(StandardError::new(format!(
             "Analysis has ended in `line {}, colume {}`.
@@ -139,15 +343,136 @@ Please check for syntax errors.",
             words[idx].row, words[idx].col
         )));
     }
+    if !block {
+        main_proc()?;
+    }
     Ok(String::new())
 }
 
 unsafe fn program_head() -> Result<String> {
     terminator(Type::Keyword, Some("program"))?;
-    terminator(Type::Identifier, None)?;
+    terminator(Type::Identifier, None)
+}
+unsafe fn type_decpart() -> Result<String> {
+    if let Ok(_) = terminator(Type::Keyword, Some("type")) {
+        loop {
+            let i = idx;
+            if let Err(e) = type_dec() {
+                if i != idx {
+                    return Err(e);
+                } else {
+                    break;
+                }
+            };
+        }
+    }
+
+    Ok(String::new())
+}
+
+unsafe fn type_dec() -> Result<String> {
+    let name = terminator(Type::Identifier, None)?;
+    vars.push(Var::new(name.clone(), "record".to_string())?);
+    terminator(Type::Operator, Some("="))?;
+    terminator(Type::Keyword, Some("record"))?;
+
+    let mut fields: Vec<Var> = Vec::new();
+    loop {
+        let i = idx;
+        let ty = match type_name() {
+            Ok(ty) => ty,
+            Err(e) => {
+                if i != idx {
+                    return Err(e);
+                }
+                break;
+            }
+        };
+        loop {
+            let field = terminator(Type::Identifier, None)?;
+            if fields.iter().any(|i| i.name == field) {
+                return Err(StandardError::new(format!(
+                    "field `{}` of `{}` has been defined",
+                    field, name
+                )));
+            }
+            fields.push(Var {
+                name: field,
+                ty: ty.clone(),
+                kind: Kind::Var,
+            });
+            if let Err(_) = terminator(Type::Separator, Some(",")) {
+                break;
+            }
+        }
+        terminator(Type::Separator, Some(";"))?;
+    }
+    if fields.is_empty() {
+        return Err(StandardError::new(format!(
+            "record `{}` has no fields",
+            name
+        )));
+    }
+    terminator(Type::Keyword, Some("end"))?;
+    terminator(Type::Separator, Some(";"))?;
+    records.push((name, fields));
+
+    Ok(String::new())
+}
+
+unsafe fn const_decpart() -> Result<String> {
+    if let Ok(_) = terminator(Type::Keyword, Some("const")) {
+        loop {
+            let i = idx;
+            if let Err(e) = const_dec() {
+                if i != idx {
+                    return Err(e);
+                } else {
+                    break;
+                }
+            };
+        }
+    }
+
+    Ok(String::new())
+}
+
+// The value is a literal or an earlier constant, possibly signed; a float
+// constant always gets a decimal point so its uses type as float.
+unsafe fn const_dec() -> Result<String> {
+    let ty = multi_terminator(Type::Keyword, &SCALAR)?;
+    loop {
+        let name = terminator(Type::Identifier, None)?;
+        let mut var = Var::new(name.clone(), ty.clone())?;
+        terminator(Type::Operator, Some("="))?;
+        let len = quaternions.len();
+        let mut val = factor()?;
+        if quaternions.len() != len || !is_literal(val.as_str()) {
+            return Err(StandardError::new(format!(
+                "line {}.\nthe value of constant `{}` must be known at compile time.",
+                words[idx - 1].row,
+                name
+            )));
+        }
+        if ty == "float" {
+            check_type(val.as_str(), &NUMERIC)?;
+            if !val.contains('.') {
+                val.push_str(".0");
+            }
+        } else {
+            check_type(val.as_str(), &[ty.as_str()])?;
+        }
+        var.kind = Kind::Const(val);
+        vars.push(var);
+        if let Err(_) = terminator(Type::Separator, Some(",")) {
+            break;
+        }
+    }
+    terminator(Type::Separator, Some(";"))?;
 
     Ok(String::new())
 }
+
 unsafe fn var_decpart() -> Result<String> {
     if let Ok(_) = terminator(Type::Keyword, Some("var")) {
         var_dec_list()?;
@@ -189,13 +514,103 @@ unsafe fn var_id_list() -> Result<String> {
 }
 
 unsafe fn type_name() -> Result<String> {
-    let ty = multi_terminator(Type::Keyword, &["integer", "float"])?;
+    if let Ok(_) = terminator(Type::Keyword, Some("array")) {
+        terminator(Type::Separator, Some("["))?;
+        let len = terminator(Type::Integer, None)?;
+        if len.parse::<usize>().map_or(true, |n| n == 0) {
+            return Err(StandardError::new(format!(
+                "line {}, column {}.\narray length must be a positive integer, found `{}`.",
+                words[idx - 1].row,
+                words[idx - 1].col,
+                len
+            )));
+        }
+        terminator(Type::Separator, Some("]"))?;
+        terminator(Type::Keyword, Some("of"))?;
+        let elem = type_name()?;
+        return Ok(format!("array[{}] of {}", len, elem));
+    }
+    if let Ok(ty) = terminator(Type::Identifier, None) {
+        if !records.iter().any(|record| record.0 == ty) {
+            return Err(StandardError::new(format!(
+                "line {}, column {}.\n`{}` is not a type.",
+                words[idx - 1].row,
+                words[idx - 1].col,
+                ty
+            )));
+        }
+        return Ok(ty);
+    }
+    let ty = multi_terminator(Type::Keyword, &SCALAR)?;
 
     Ok(ty)
 }
 
 unsafe fn program_body() -> Result<String> {
     swap(&mut global_vars, &mut vars);
+    proc_decpart()
+}
+
+// The program's own statements run as a procedure named after it, which
+// becomes the entry point.
+unsafe fn main_block(name: String) -> Result<bool> {
+    if let Err(_) = terminator(Type::Keyword, Some("begin")) {
+        return Ok(false);
+    }
+    if proc_list.iter().any(|proc| proc.name == name && proc.parent.is_none()) {
+        return Err(StandardError::new(format!("`{}` has been defined", name)));
+    }
+    current = proc_list.len();
+    proc_list.push(Proc {
+        name,
+        quaternions: Vec::new(),
+        params: Vec::new(),
+        vars: Vec::new(),
+        ret: None,
+        parent: None,
+    });
+    stm_list()?;
+    terminator(Type::Keyword, Some("end"))?;
+    terminator(Type::Separator, Some("."))?;
+
+    swap(&mut proc_list[current].quaternions, &mut quaternions);
+    temp_gen.reset();
+    entry = current;
+
+    Ok(true)
+}
+
+// The procedure `name` calls from inside `proc` refer to: one nested in it,
+// else in the nearest enclosing procedure that has one, else at the top.
+unsafe fn callee(proc: usize, name: &str) -> Option<usize> {
+    let mut scope = Some(proc);
+    loop {
+        if let Some(i) = proc_list.iter().position(|p| p.name == name && p.parent == scope) {
+            return Some(i);
+        }
+        scope = proc_list[scope?].parent;
+    }
+}
+
+// Without a main block, execution starts from a top-level `procedure main()`.
+unsafe fn main_proc() -> Result<String> {
+    match proc_list
+        .iter()
+        .position(|proc| proc.name == "main" && proc.parent.is_none() && proc.ret.is_none())
+    {
+        Some(main) if proc_list[main].params.is_empty() => {
+            entry = main;
+            Ok(String::new())
+        }
+        _ => Err(StandardError::new(
+            "no entry point: expected a `begin ... end.` block or a `procedure main()`."
+                .to_string(),
+        )),
+    }
+}
+
+unsafe fn proc_decpart() -> Result<String> {
+    proc_signatures()?;
     let mut i = idx;
     match proc_dec() {
         Err(e) => {
@@ -218,26 +633,108 @@ unsafe fn program_body() -> Result<String> {
     Ok(String::new())
 }
 
-unsafe fn proc_dec() -> Result<String> {
-    terminator(Type::Keyword, Some("procedure"))?;
-    let name = terminator(Type::Identifier, None)?;
+// First pass: register every procedure of this level with its params before
+// any body is checked, so calls do not depend on declaration order. Nested
+// declarations are skipped here and registered when their parent is parsed.
+unsafe fn proc_signatures() -> Result<String> {
+    let i = idx;
+    let mut depth = 0;
+    while idx < words.len() {
+        if words[idx].ty == Type::Keyword && words[idx].val == "end" {
+            if depth == 0 {
+                break;
+            }
+            depth -= 1;
+        }
+        if words[idx].ty != Type::Keyword
+            || (words[idx].val != "procedure" && words[idx].val != "function")
+        {
+            idx += 1;
+            continue;
+        }
+        if depth > 0 {
+            depth += 1;
+            idx += 1;
+            continue;
+        }
+        depth += 1;
+        let (kind, name, ret) = proc_head()?;
+        let var = Var::new(name.clone(), kind)?;
+        // procedures in different scopes may share a name
+        if proc_list.iter().any(|proc| proc.name == name && proc.parent == scopes.last().copied()) {
+            return Err(StandardError::new(format!("`{}` has been defined", name)));
+        }
+        match scopes.last() {
+            Some(&parent) => proc_list[parent].vars.push(var),
+            None => global_vars.push(var),
+        }
+        proc_list.push(Proc {
+            name,
+            quaternions: Vec::new(),
+            params: Vec::new(),
+            vars: Vec::new(),
+            ret,
+            parent: scopes.last().copied(),
+        });
+        swap(&mut proc_list.last_mut().unwrap().params, &mut params);
+    }
+    idx = i;
 
-    global_vars.push(Var::new(name.clone(), "procedure".to_string())?);
-    proc_list.push((name, Vec::new(), Vec::new(), Vec::new()));
+    Ok(String::new())
+}
 
+unsafe fn proc_head() -> Result<(String, String, Option<String>)> {
+    let kind = multi_terminator(Type::Keyword, &["procedure", "function"])?;
+    let name = terminator(Type::Identifier, None)?;
     terminator(Type::Separator, Some("("))?;
     param_list()?;
     terminator(Type::Separator, Some(")"))?;
+    let mut ret = None;
+    if kind == "function" {
+        terminator(Type::Separator, Some(":"))?;
+        ret = Some(multi_terminator(Type::Keyword, &SCALAR)?);
+    }
+
+    Ok((kind, name, ret))
+}
+
+unsafe fn proc_dec() -> Result<String> {
+    let (kind, name, _) = proc_head()?;
+    let this = proc_list
+        .iter()
+        .position(|proc| proc.name == name && proc.parent == scopes.last().copied())
+        .unwrap();
     // if unmatched, output error message and continue.
     if let Err(e) = terminator(Type::Separator, Some(";")) {
         println!("{}", e)
     };
+    const_decpart()?;
     var_decpart()?;
+
+    // the first pass already stored the params in `proc_list`
+    params.clear();
+    swap(&mut proc_list[this].vars, &mut vars);
+    scopes.push(this);
+    proc_decpart()?;
+    scopes.pop();
+    params = proc_list[this].params.clone();
+    swap(&mut proc_list[this].vars, &mut vars);
+
+    current = this;
     proc_body()?;
 
-    swap(&mut proc_list.last_mut().unwrap().1, &mut quaternions);
-    swap(&mut proc_list.last_mut().unwrap().2, &mut params);
-    swap(&mut proc_list.last_mut().unwrap().3, &mut vars);
+    if kind == "function" && !quaternions.iter().any(|q| q.op == "ret") {
+        return Err(StandardError::new(format!(
+            "function `{}` has no `return` statement",
+            name
+        )));
+    }
+
+    let proc = &mut proc_list[current];
+    swap(&mut proc.quaternions, &mut quaternions);
+    swap(&mut proc.params, &mut params);
+    swap(&mut proc.vars, &mut vars);
+    params.clear();
     temp_gen.reset();
 
     Ok(String::new())
@@ -262,10 +759,15 @@ unsafe fn param_list() -> Result<String> {
 }
 
 unsafe fn param() -> Result<String> {
+    let by_ref = terminator(Type::Keyword, Some("var")).is_ok();
     let ty = type_name()?;
     loop {
         let name = terminator(Type::Identifier, None)?;
-        params.push(Var::new(name, ty.clone())?);
+        let mut var = Var::new(name, ty.clone())?;
+        if by_ref {
+            var.kind = Kind::Ref;
+        }
+        params.push(var);
         if let Err(_) = terminator(Type::Separator, Some(",")) {
             break;
         }
@@ -311,6 +813,15 @@ unsafe fn stm() -> Result<String> {
         }
     };
 
+    match case_stm() {
+        Ok(v) => return Ok(v),
+        Err(e) => {
+            if i != idx {
+                return Err(e);
+            }
+        }
+    };
+
     match loop_stm() {
         Ok(v) => return Ok(v),
         Err(e) => {
@@ -319,6 +830,24 @@ unsafe fn stm() -> Result<String> {
             }
         }
     };
+
+    match for_stm() {
+        Ok(v) => return Ok(v),
+        Err(e) => {
+            if i != idx {
+                return Err(e);
+            }
+        }
+    };
+
+    match repeat_stm() {
+        Ok(v) => return Ok(v),
+        Err(e) => {
+            if i != idx {
+                return Err(e);
+            }
+        }
+    };
     match input_stm() {
         Ok(v) => return Ok(v),
         Err(e) => {
@@ -337,6 +866,24 @@ unsafe fn stm() -> Result<String> {
         }
     };
 
+    match return_stm() {
+        Ok(v) => return Ok(v),
+        Err(e) => {
+            if i != idx {
+                return Err(e);
+            }
+        }
+    };
+
+    match jump_stm() {
+        Ok(v) => return Ok(v),
+        Err(e) => {
+            if i != idx {
+                return Err(e);
+            }
+        }
+    };
+
     match call_stm() {
         Ok(v) => return Ok(v),
         Err(e) => {
@@ -363,7 +910,8 @@ unsafe fn stm() -> Result<String> {
 unsafe fn input_stm() -> Result<String> {
     let op = terminator(Type::Keyword, Some("read"))?;
     let result = terminator(Type::Identifier, None)?;
-    check_undef(result.as_str(), &["integer", "float"])?;
+    check_undef(result.as_str(), &SCALAR)?;
+    check_var(result.as_str())?;
     quaternions.push(Quaternion {
         op,
         arg1: "_".to_string(),
@@ -375,8 +923,8 @@ unsafe fn input_stm() -> Result<String> {
 
 unsafe fn output_stm() -> Result<String> {
     let op = terminator(Type::Keyword, Some("write"))?;
-    let arg1 = exp()?;
-    check_undef(arg1.as_str(), &["integer", "float"])?;
+    let arg1 = conditional_exp()?;
+    check_type(arg1.as_str(), &SCALAR)?;
     quaternions.push(Quaternion {
         op,
         arg1,
@@ -386,16 +934,59 @@ unsafe fn output_stm() -> Result<String> {
     Ok(String::new())
 }
 
+unsafe fn return_stm() -> Result<String> {
+    terminator(Type::Keyword, Some("return"))?;
+    let mut arg1 = "_".to_string();
+    if let Some(ty) = &proc_list[current].ret {
+        arg1 = conditional_exp()?;
+        check_assign(ty.as_str(), arg1.as_str())?;
+    }
+    quaternions.push(Quaternion {
+        op: "ret".to_string(),
+        arg1,
+        arg2: "_".to_string(),
+        result: "_".to_string(),
+    });
+    Ok(String::new())
+}
+
 unsafe fn call_stm() -> Result<String> {
     let arg1 = terminator(Type::Identifier, None)?;
-    check_undef(arg1.as_str(), &["procedure"])?;
+    check_undef(arg1.as_str(), &["procedure", "function"])?;
+    proc_call(arg1)?;
+    Ok(String::new())
+}
+
+// Parses `'(' ActParamList ')'` after the callee name and emits `par`s and `call`.
+unsafe fn proc_call(name: String) -> Result<String> {
     terminator(Type::Separator, Some("("))?;
-    act_param_list()?;
+    let formals = proc_list[callee(current, &name).unwrap()].params.clone();
+    let args = act_param_list(&formals)?;
     terminator(Type::Separator, Some(")"))?;
+    if args.len() != formals.len() {
+        return Err(StandardError::new(format!(
+            "`{}` takes {} argument(s), but {} were supplied",
+            name,
+            formals.len(),
+            args.len()
+        )));
+    }
+    for (arg, param) in args.iter().zip(formals.iter()) {
+        let arg = arg.arg1.as_str();
+        if param.kind == Kind::Ref {
+            continue;
+        }
+        if !is_scalar(param.ty.as_str()) || var_type(arg).is_ok_and(|ty| !is_scalar(&ty)) {
+            check_undef(arg, &[param.ty.as_str()])?;
+        } else {
+            check_assign(param.ty.as_str(), arg)?;
+        }
+    }
+    quaternions.extend(args.iter().cloned());
     quaternions.push(Quaternion {
         op: "call".to_string(),
-        arg1,
-        arg2: "_".to_string(),
+        arg1: name,
+        arg2: args.len().to_string(),
         result: "_".to_string(),
     });
     Ok(String::new())
@@ -403,12 +994,25 @@ unsafe fn call_stm() -> Result<String> {
 
 unsafe fn assignment_stm() -> Result<String> {
     let result = terminator(Type::Identifier, None)?;
-    if let Err(e) = check_undef(result.as_str(), &["integer", "float"]) {
+    if let Some((arg2, ty)) = selector(result.as_str())? {
+        terminator(Type::Operator, Some("="))?;
+        let arg1 = conditional_exp()?;
+        check_assign(ty.as_str(), arg1.as_str())?;
+        quaternions.push(Quaternion {
+            op: "[]=".to_string(),
+            arg1,
+            arg2,
+            result,
+        });
+        return Ok(String::new());
+    }
+    if let Err(e) = check_undef(result.as_str(), &SCALAR).and(check_var(result.as_str())) {
         idx += 1; //for output error
         return Err(e);
     }
     let op = terminator(Type::Operator, Some("="))?;
-    let arg1 = exp()?;
+    let arg1 = conditional_exp()?;
+    check_assign(type_of(result.as_str()).as_str(), arg1.as_str())?;
     quaternions.push(Quaternion {
         op,
         arg1,
@@ -420,47 +1024,233 @@ unsafe fn assignment_stm() -> Result<String> {
 
 unsafe fn conditional_stm() -> Result<String> {
     terminator(Type::Keyword, Some("if"))?;
-    let arg1 = conditional_exp()?;
 
-    quaternions.push(Quaternion {
-        op: "jnz".to_string(),
-        arg1,
-        arg2: "_".to_string(),
-        result: (quaternions.len() + 2).to_string(),
-    });
+    // jumps from the end of each taken branch past the whole statement
+    let mut exits = Vec::new();
+    loop {
+        let arg1 = conditional_exp()?;
+        check_type(arg1.as_str(), &["boolean"])?;
 
-    let a1 = quaternions.len();
-    quaternions.push(Quaternion {
-        op: "j".to_string(),
-        arg1: "_".to_string(),
-        arg2: "_".to_string(),
-        result: String::new(),
-    });
+        quaternions.push(Quaternion {
+            op: "jnz".to_string(),
+            arg1,
+            arg2: "_".to_string(),
+            result: (quaternions.len() + 2).to_string(),
+        });
 
-    terminator(Type::Keyword, Some("then"))?;
-    stm_list()?;
-    let a2 = quaternions.len();
-    quaternions.push(Quaternion {
-        op: "j".to_string(),
-        arg1: "_".to_string(),
-        arg2: "_".to_string(),
-        result: String::new(),
-    });
-    quaternions[a1].result = quaternions.len().to_string();
+        let a1 = quaternions.len();
+        quaternions.push(Quaternion {
+            op: "j".to_string(),
+            arg1: "_".to_string(),
+            arg2: "_".to_string(),
+            result: String::new(),
+        });
 
-    // if unmatched, output error message and continue.
-    match terminator(Type::Keyword, Some("else")) {
-        Ok(_) => {
+        terminator(Type::Keyword, Some("then"))?;
+        stm_list()?;
+
+        // without a following branch there is nothing to jump over
+        let next = multi_terminator(Type::Keyword, &["elsif", "else"]);
+        if next.is_ok() {
+            exits.push(quaternions.len());
+            quaternions.push(Quaternion {
+                op: "j".to_string(),
+                arg1: "_".to_string(),
+                arg2: "_".to_string(),
+                result: String::new(),
+            });
+        }
+        quaternions[a1].result = quaternions.len().to_string();
+
+        match next {
+            Ok(v) if v == "elsif" => continue,
+            Ok(_) => stm_list()?,
+            Err(_) => String::new(),
+        };
+        break;
+    }
+
+    for i in exits {
+        quaternions[i].result = quaternions.len().to_string();
+    }
+
+    terminator(Type::Keyword, Some("fi"))?;
+
+    Ok(String::new())
+}
+
+// Arms are laid out in order, each followed by a jump to the end; the
+// dispatch comes last, as a jump table when that is shorter than a chain
+// of comparisons.
+unsafe fn case_stm() -> Result<String> {
+    terminator(Type::Keyword, Some("case"))?;
+    let arg1 = exp()?;
+    check_type(arg1.as_str(), &["integer"])?;
+    terminator(Type::Keyword, Some("of"))?;
+
+    let a1 = quaternions.len();
+    quaternions.push(Quaternion {
+        op: "j".to_string(),
+        arg1: "_".to_string(),
+        arg2: "_".to_string(),
+        result: String::new(),
+    });
+
+    let mut labels: Vec<(i64, usize)> = Vec::new();
+    let mut exits = Vec::new();
+    let mut default = None;
+    loop {
+        if terminator(Type::Keyword, Some("else")).is_ok() {
+            default = Some(quaternions.len());
             stm_list()?;
+            break;
         }
-        Err(e) => {
-            println!("{}", e);
+        loop {
+            let row = words[idx].row;
+            let label = case_label()?;
+            if labels.iter().any(|i| i.0 == label) {
+                return Err(StandardError::new(format!(
+                    "line {}.\nduplicate label `{}` in `case`.",
+                    row, label
+                )));
+            }
+            labels.push((label, quaternions.len()));
+            if terminator(Type::Separator, Some(",")).is_err() {
+                break;
+            }
         }
-    };
+        terminator(Type::Separator, Some(":"))?;
+        arm_list()?;
+        exits.push(quaternions.len());
+        quaternions.push(Quaternion {
+            op: "j".to_string(),
+            arg1: "_".to_string(),
+            arg2: "_".to_string(),
+            result: String::new(),
+        });
+        if terminator(Type::Separator, Some(";")).is_err() {
+            break;
+        }
+    }
+    terminator(Type::Keyword, Some("esac"))?;
 
-    quaternions[a2].result = quaternions.len().to_string();
+    // the default arm comes last and must jump over the dispatch
+    if default.is_some() {
+        exits.push(quaternions.len());
+        quaternions.push(Quaternion {
+            op: "j".to_string(),
+            arg1: "_".to_string(),
+            arg2: "_".to_string(),
+            result: String::new(),
+        });
+    }
+    quaternions[a1].result = quaternions.len().to_string();
 
-    terminator(Type::Keyword, Some("fi"))?;
+    let table = jump_table(&labels);
+    let end = quaternions.len()
+        + match table {
+            Some((min, max)) => max.abs_diff(min) as usize + 3,
+            None => 2 * labels.len() + 1,
+        };
+    let default = default.unwrap_or(end);
+    if let Some((min, max)) = table {
+        quaternions.push(Quaternion {
+            op: "jtab".to_string(),
+            arg1,
+            arg2: min.to_string(),
+            result: max.to_string(),
+        });
+        for label in min..=max {
+            let target = match labels.iter().find(|i| i.0 == label) {
+                Some(i) => i.1,
+                None => default,
+            };
+            quaternions.push(Quaternion {
+                op: "j".to_string(),
+                arg1: "_".to_string(),
+                arg2: "_".to_string(),
+                result: target.to_string(),
+            });
+        }
+    } else {
+        for (label, target) in labels.iter() {
+            let t = temp_gen.gen("boolean");
+            quaternions.push(Quaternion {
+                op: "==".to_string(),
+                arg1: arg1.clone(),
+                arg2: label.to_string(),
+                result: t.clone(),
+            });
+            quaternions.push(Quaternion {
+                op: "jnz".to_string(),
+                arg1: t,
+                arg2: "_".to_string(),
+                result: target.to_string(),
+            });
+        }
+    }
+    quaternions.push(Quaternion {
+        op: "j".to_string(),
+        arg1: "_".to_string(),
+        arg2: "_".to_string(),
+        result: default.to_string(),
+    });
+    for i in exits {
+        quaternions[i].result = end.to_string();
+    }
+
+    Ok(String::new())
+}
+
+// The label range, if a `jtab` with one entry per value in it is shorter
+// than a comparison and a jump per label.
+fn jump_table(labels: &[(i64, usize)]) -> Option<(i64, i64)> {
+    let min = labels.iter().map(|i| i.0).min()?;
+    let max = labels.iter().map(|i| i.0).max()?;
+    if max.abs_diff(min) + 2 < 2 * labels.len() as u64 {
+        Some((min, max))
+    } else {
+        None
+    }
+}
+
+unsafe fn case_label() -> Result<i64> {
+    let sign = multi_terminator(Type::Operator, &["+", "-"]).unwrap_or_default();
+    let val = terminator(Type::Integer, None)?;
+    format!("{}{}", sign, val).parse().map_err(|_| {
+        StandardError::new(format!(
+            "line {}, column {}.\nlabel `{}{}` is out of range.",
+            words[idx - 1].row,
+            words[idx - 1].col,
+            sign,
+            val
+        ))
+    })
+}
+
+// Like `stm_list`, but a `;` followed by a label, `else` or `esac` ends the arm.
+unsafe fn arm_list() -> Result<String> {
+    let i = idx;
+    match stm() {
+        Err(e) => {
+            if i != idx {
+                return Err(e);
+            }
+        }
+        Ok(_) => {
+            while idx + 1 < words.len() && words[idx].val == ";" {
+                let next = &words[idx + 1];
+                if next.ty == Type::Integer
+                    || next.ty == Type::Operator
+                    || ["else", "esac"].contains(&next.val.as_str())
+                {
+                    break;
+                }
+                idx += 1;
+                stm()?;
+            }
+        }
+    }
 
     Ok(String::new())
 }
@@ -469,7 +1259,9 @@ unsafe fn loop_stm() -> Result<String> {
     let a1 = quaternions.len();
 
     terminator(Type::Keyword, Some("while"))?;
+    loops.push((Vec::new(), Vec::new()));
     let arg1 = conditional_exp()?;
+    check_type(arg1.as_str(), &["boolean"])?;
 
     let a2 = quaternions.len();
     quaternions.push(Quaternion {
@@ -489,29 +1281,259 @@ unsafe fn loop_stm() -> Result<String> {
         result: a1.to_string(),
     });
     quaternions[a2].result = quaternions.len().to_string();
+    close_loop(a1, quaternions.len());
 
     terminator(Type::Keyword, Some("endwh"))?;
 
     Ok(String::new())
 }
 
-unsafe fn act_param_list() -> Result<String> {
+// The loop runs while the variable is `<=` the limit, or `>=` it for a
+// negative constant step. Limit and step are evaluated once, before the loop.
+unsafe fn for_stm() -> Result<String> {
+    terminator(Type::Keyword, Some("for"))?;
+    let result = terminator(Type::Identifier, None)?;
+    check_undef(result.as_str(), &["integer"])?;
+    check_var(result.as_str())?;
+    terminator(Type::Operator, Some("="))?;
+    let arg1 = exp()?;
+    check_type(arg1.as_str(), &["integer"])?;
+    terminator(Type::Keyword, Some("to"))?;
+    let limit = loop_bound()?;
+    let mut step = "1".to_string();
+    if let Ok(_) = terminator(Type::Keyword, Some("step")) {
+        step = loop_bound()?;
+        // the step's sign picks the comparison against the limit
+        if !is_literal(step.as_str()) {
+            return Err(StandardError::new(format!(
+                "line {}.\n`step` must be a constant.",
+                words[idx - 1].row
+            )));
+        }
+        if step == "0" {
+            return Err(StandardError::new(format!(
+                "line {}.\n`step` must not be zero.",
+                words[idx - 1].row
+            )));
+        }
+    }
+    quaternions.push(Quaternion {
+        op: "=".to_string(),
+        arg1,
+        arg2: "_".to_string(),
+        result: result.clone(),
+    });
+
+    let a1 = quaternions.len();
+    let cond = temp_gen.gen("boolean");
+    quaternions.push(Quaternion {
+        op: if step.starts_with('-') { ">=" } else { "<=" }.to_string(),
+        arg1: result.clone(),
+        arg2: limit,
+        result: cond.clone(),
+    });
+    let a2 = quaternions.len();
+    quaternions.push(Quaternion {
+        op: "jez".to_string(),
+        arg1: cond,
+        arg2: "_".to_string(),
+        result: String::new(),
+    });
+
+    terminator(Type::Keyword, Some("do"))?;
+    loops.push((Vec::new(), Vec::new()));
+    stm_list()?;
+
+    let a3 = quaternions.len();
+    let next = temp_gen.gen("integer");
+    quaternions.push(Quaternion {
+        op: "+".to_string(),
+        arg1: result.clone(),
+        arg2: step,
+        result: next.clone(),
+    });
+    quaternions.push(Quaternion {
+        op: "=".to_string(),
+        arg1: next,
+        arg2: "_".to_string(),
+        result,
+    });
+    quaternions.push(Quaternion {
+        op: "j".to_string(),
+        arg1: "_".to_string(),
+        arg2: "_".to_string(),
+        result: a1.to_string(),
+    });
+    quaternions[a2].result = quaternions.len().to_string();
+    close_loop(a3, quaternions.len());
+
+    terminator(Type::Keyword, Some("endfor"))?;
+
+    Ok(String::new())
+}
+
+// An integer `Exp` whose value must not change while the loop runs, so a
+// variable is copied into a temp.
+unsafe fn loop_bound() -> Result<String> {
+    let val = exp()?;
+    check_type(val.as_str(), &["integer"])?;
+    if temp_gen.contains(val.as_str()) || val.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        return Ok(val);
+    }
+    let result = temp_gen.gen("integer");
+    quaternions.push(Quaternion {
+        op: "=".to_string(),
+        arg1: val,
+        arg2: "_".to_string(),
+        result: result.clone(),
+    });
+    Ok(result)
+}
+
+unsafe fn repeat_stm() -> Result<String> {
+    terminator(Type::Keyword, Some("repeat"))?;
+    loops.push((Vec::new(), Vec::new()));
+    let a1 = quaternions.len();
+    stm_list()?;
+    terminator(Type::Keyword, Some("until"))?;
+    let a2 = quaternions.len();
+    let arg1 = conditional_exp()?;
+    check_type(arg1.as_str(), &["boolean"])?;
+    quaternions.push(Quaternion {
+        op: "jez".to_string(),
+        arg1,
+        arg2: "_".to_string(),
+        result: a1.to_string(),
+    });
+    close_loop(a2, quaternions.len());
+
+    Ok(String::new())
+}
+
+// `break` jumps to the loop's exit, `continue` to where its next iteration starts.
+unsafe fn jump_stm() -> Result<String> {
+    let op = multi_terminator(Type::Keyword, &["break", "continue"])?;
+    let jumps = match loops.last_mut() {
+        Some(jumps) => jumps,
+        None => {
+            return Err(StandardError::new(format!(
+                "line {}, column {}.\n`{}` outside of a loop.",
+                words[idx - 1].row,
+                words[idx - 1].col,
+                op
+            )))
+        }
+    };
+    if op == "break" {
+        jumps.0.push(quaternions.len());
+    } else {
+        jumps.1.push(quaternions.len());
+    }
+    quaternions.push(Quaternion {
+        op: "j".to_string(),
+        arg1: "_".to_string(),
+        arg2: "_".to_string(),
+        result: String::new(),
+    });
+
+    Ok(String::new())
+}
+
+// Backpatches the pending jumps of the innermost loop.
+unsafe fn close_loop(head: usize, exit: usize) {
+    let (breaks, continues) = loops.pop().unwrap();
+    for i in breaks {
+        quaternions[i].result = exit.to_string();
+    }
+    for i in continues {
+        quaternions[i].result = head.to_string();
+    }
+}
+
+// Returns the `par` or `ref` quaternion of each argument, to be emitted
+// once all of them are evaluated.
+unsafe fn act_param_list(formals: &[Var]) -> Result<Vec<Quaternion>> {
     let i = idx;
-    match exp() {
+    let mut args = Vec::new();
+    match act_param(formals.first()) {
         Err(e) => {
-            if i != idx {
+            // only `()` is an empty list
+            if i != idx || words.get(idx).is_some_and(|word| word.val != ")") {
                 return Err(e);
             }
         }
-        Ok(_) => loop {
-            if let Err(_) = terminator(Type::Separator, Some(",")) {
-                break;
+        Ok(arg) => {
+            args.push(arg);
+            loop {
+                if let Err(_) = terminator(Type::Separator, Some(",")) {
+                    break;
+                }
+                args.push(act_param(formals.get(args.len()))?);
             }
-            exp()?;
-        },
+        }
     }
 
-    Ok(String::new())
+    Ok(args)
+}
+
+// A whole array or record is passed by naming it; anything else is an `Exp`.
+unsafe fn act_param(param: Option<&Var>) -> Result<Quaternion> {
+    if let Some(param) = param.filter(|param| param.kind == Kind::Ref) {
+        return ref_param(param);
+    }
+    let arg1 = if idx + 1 < words.len()
+        && words[idx].ty == Type::Identifier
+        && (words[idx + 1].val == "," || words[idx + 1].val == ")")
+        && var_type(words[idx].val.as_str()).is_ok_and(|ty| !is_scalar(&ty))
+    {
+        terminator(Type::Identifier, None)?
+    } else {
+        conditional_exp()?
+    };
+    Ok(Quaternion {
+        op: "par".to_string(),
+        arg1,
+        arg2: "_".to_string(),
+        result: "_".to_string(),
+    })
+}
+
+// The argument of a `var` param must be a variable, or a component of one,
+// of exactly the param's type; its slot offset goes in `arg2`.
+unsafe fn ref_param(param: &Var) -> Result<Quaternion> {
+    let (row, col) = match words.get(idx) {
+        Some(word) => (word.row, word.col),
+        None => return Err(StandardError::new("End where it should not end".to_string())),
+    };
+    let not_var = || {
+        StandardError::new(format!(
+            "line {}, column {}.\nthe argument of `var` param `{}` must be a variable.",
+            row, col, param.name
+        ))
+    };
+    let arg1 = terminator(Type::Identifier, None).map_err(|_| not_var())?;
+    if !matches!(symbol(arg1.as_str()), Some(Var { kind: Kind::Var | Kind::Ref, .. })) {
+        return Err(not_var());
+    }
+    let (arg2, ty) = match selector(arg1.as_str())? {
+        Some(v) => v,
+        None => ("_".to_string(), var_type(arg1.as_str())?),
+    };
+    if idx < words.len() && words[idx].val != "," && words[idx].val != ")" {
+        return Err(not_var());
+    }
+    if ty != param.ty {
+        return Err(StandardError::new(format!(
+            "line {}.\nexpected Type `{}`, found Type `{}`.",
+            row, param.ty, ty
+        )));
+    }
+    Ok(Quaternion {
+        op: "ref".to_string(),
+        arg1,
+        arg2,
+        result: "_".to_string(),
+    })
 }
 
 unsafe fn exp() -> Result<String> {
@@ -524,7 +1546,7 @@ unsafe fn exp() -> Result<String> {
             Err(_) => break,
         }
         let arg2 = term()?;
-        let result = temp_gen.gen();
+        let result = temp_gen.gen(arith_type(arg1.as_str(), arg2.as_str())?.as_str());
         quaternions.push(Quaternion {
             op,
             arg1: arg1.clone(),
@@ -542,12 +1564,17 @@ unsafe fn term() -> Result<String> {
 
     loop {
         let op;
-        match multi_terminator(Type::Operator, &["*", "/"]) {
+        match multi_terminator(Type::Operator, &["*", "/", "%"]) {
             Ok(val) => op = val,
             Err(_) => break,
         }
         let arg2 = factor()?;
-        let result = temp_gen.gen();
+        let ty = arith_type(arg1.as_str(), arg2.as_str())?;
+        if op == "%" {
+            check_type(arg1.as_str(), &["integer"])?;
+            check_type(arg2.as_str(), &["integer"])?;
+        }
+        let result = temp_gen.gen(ty.as_str());
         quaternions.push(Quaternion {
             op,
             arg1: arg1.clone(),
@@ -561,27 +1588,84 @@ unsafe fn term() -> Result<String> {
 }
 
 unsafe fn factor() -> Result<String> {
-    let val = match terminator(Type::Identifier, None) {
-        Ok(val) => {
-            check_undef(val.as_str(), &["integer", "float"])?;
-            val
+    if let Ok(op) = multi_terminator(Type::Operator, &["+", "-"]) {
+        let arg1 = factor()?;
+        let ty = check_type(arg1.as_str(), &NUMERIC)?;
+        if op == "+" {
+            return Ok(arg1);
+        }
+        // negative literals need no quaternion
+        if arg1.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok(format!("-{}", arg1));
+        }
+        if let Some(val) = arg1.strip_prefix('-') {
+            return Ok(val.to_string());
         }
+        let result = temp_gen.gen(ty.as_str());
+        quaternions.push(Quaternion {
+            op: "neg".to_string(),
+            arg1,
+            arg2: "_".to_string(),
+            result: result.clone(),
+        });
+        return Ok(result);
+    }
+
+    let val = match terminator(Type::Identifier, None) {
+        Ok(val) => match terminator(Type::Separator, Some("(")) {
+            Ok(_) => {
+                check_undef(val.as_str(), &["function"])?;
+                idx -= 1;
+                let ty = proc_list[callee(current, &val).unwrap()].ret.clone();
+                proc_call(val)?;
+                let result = temp_gen.gen(ty.unwrap().as_str());
+                quaternions.push(Quaternion {
+                    op: "retval".to_string(),
+                    arg1: "_".to_string(),
+                    arg2: "_".to_string(),
+                    result: result.clone(),
+                });
+                result
+            }
+            Err(_) => match selector(val.as_str())? {
+                Some((arg2, ty)) => {
+                    let result = temp_gen.gen(ty.as_str());
+                    quaternions.push(Quaternion {
+                        op: "=[]".to_string(),
+                        arg1: val,
+                        arg2,
+                        result: result.clone(),
+                    });
+                    result
+                }
+                None => {
+                    check_undef(val.as_str(), &SCALAR)?;
+                    match &symbol(val.as_str()).unwrap().kind {
+                        Kind::Const(val) => val.clone(),
+                        _ => val,
+                    }
+                }
+            },
+        },
         Err(_) => match terminator(Type::Integer, None) {
             Ok(val) => val,
             Err(_) => match terminator(Type::FloatPoint, None) {
                 Ok(val) => val,
-                Err(_) => {
-                    match terminator(Type::Separator, Some("(")) {
-                        Ok(_) => {
-                            let mut tmp = exp();
-                            if tmp.is_ok() {
-                                tmp = tmp.and(terminator(Type::Separator, Some(")")));
+                Err(_) => match multi_terminator(Type::Keyword, &["true", "false"]) {
+                    Ok(val) => val,
+                    Err(_) => {
+                        match terminator(Type::Separator, Some("(")) {
+                            Ok(_) => {
+                                let tmp = conditional_exp();
+                                if tmp.is_ok() {
+                                    terminator(Type::Separator, Some(")"))?;
+                                }
+                                tmp
                             }
-                            tmp
+                            Err(e) => Err(e),
                         }
-                        Err(e) => Err(e),
-                    }
-                }?,
+                    }?,
+                },
             },
         },
     };
@@ -601,6 +1685,157 @@ unsafe fn factor() -> Result<String> {
     Ok(val)
 }
 
+// Parses `{'[' Exp ']' | '.' ID}` after `ID` down to a scalar component and
+// returns its slot offset within `name` and its type, or `None` without
+// selectors. Constant indices, folded if need be, are checked here, others get a `bound`
+// quaternion carrying the source line for the interpreter.
+unsafe fn selector(name: &str) -> Result<Option<(String, String)>> {
+    if idx == words.len() || (words[idx].val != "[" && words[idx].val != ".") {
+        return Ok(None);
+    }
+    let row = words[idx].row;
+    let mut ty = var_type(name)?;
+    let mut offset = "0".to_string();
+    loop {
+        let part = if let Ok(_) = terminator(Type::Separator, Some("[")) {
+            let (len, elem) = match array_type(ty.as_str()) {
+                Some((len, elem)) => (len, elem.to_string()),
+                None => {
+                    return Err(StandardError::new(format!(
+                        "line {}.\nType `{}` cannot be indexed.",
+                        row, ty
+                    )))
+                }
+            };
+            let start = (quaternions.len(), temp_gen.n);
+            let index = fold_exp(start, exp()?);
+            terminator(Type::Separator, Some("]"))?;
+            check_type(index.as_str(), &["integer"])?;
+            check_index(name, index.as_str(), len, row)?;
+            let width = size_of(elem.as_str());
+            ty = elem;
+            address(index, "*", width.to_string())
+        } else if let Ok(_) = terminator(Type::Separator, Some(".")) {
+            let field = terminator(Type::Identifier, None)?;
+            let (field_offset, field_ty) = match record_field(ty.as_str(), field.as_str()) {
+                Some(v) => v,
+                None => {
+                    return Err(StandardError::new(format!(
+                        "line {}.\nType `{}` has no field `{}`.",
+                        row, ty, field
+                    )))
+                }
+            };
+            ty = field_ty;
+            field_offset.to_string()
+        } else {
+            break;
+        };
+        offset = address(offset, "+", part);
+    }
+    if !is_scalar(ty.as_str()) {
+        return Err(StandardError::new(format!(
+            "line {}.\nexpected Type `{}`, found Type `{}`.",
+            row,
+            SCALAR.join(", "),
+            ty
+        )));
+    }
+
+    Ok(Some((offset, ty)))
+}
+
+// The literal an expression comes to when the quaternions computing it,
+// those after the first `start.0`, only combine literals; they are dropped
+// then, with the temps after the first `start.1`.
+unsafe fn fold_exp(start: (usize, usize), val: String) -> String {
+    let mut values: HashMap<String, String> = HashMap::new();
+    for q in quaternions[start.0..].iter() {
+        let mut q = q.clone();
+        for arg in operands_mut(&mut q) {
+            if let Some(v) = values.get(arg.as_str()) {
+                *arg = v.clone();
+            }
+        }
+        fold(&mut q);
+        if q.op != "=" || !is_literal(&q.arg1) || !q.result.starts_with('@') {
+            return val;
+        }
+        values.insert(q.result, q.arg1);
+    }
+    match values.remove(&val) {
+        Some(v) => {
+            quaternions.truncate(start.0);
+            temp_gen.rewind(start.1);
+            v
+        }
+        None => val,
+    }
+}
+
+unsafe fn check_index(name: &str, index: &str, len: usize, row: usize) -> Result<()> {
+    if index.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        match index.parse::<i64>() {
+            Ok(i) if 0 <= i && (i as usize) < len => {}
+            Ok(i) => {
+                return Err(StandardError::new(format!(
+                    "line {}.\nindex {} is out of bounds for `{}` of length {}.",
+                    row, i, name, len
+                )))
+            }
+            Err(_) => {
+                return Err(StandardError::new(format!(
+                    "line {}.\nindex of `{}` must be an integer, found `{}`.",
+                    row, name, index
+                )))
+            }
+        }
+    } else {
+        quaternions.push(Quaternion {
+            op: "bound".to_string(),
+            arg1: index.to_string(),
+            arg2: len.to_string(),
+            result: row.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+// Emits one step of an address computation; constant parts are computed here.
+unsafe fn address(arg1: String, op: &str, arg2: String) -> String {
+    match (arg1.parse::<usize>(), arg2.parse::<usize>()) {
+        (Ok(a), Ok(b)) if op == "+" => (a + b).to_string(),
+        (Ok(a), Ok(b)) => (a * b).to_string(),
+        (Ok(0), _) if op == "+" => arg2,
+        (_, Ok(0)) if op == "+" => arg1,
+        (_, Ok(1)) if op == "*" => arg1,
+        _ => {
+            let result = temp_gen.gen("integer");
+            quaternions.push(Quaternion {
+                op: op.to_string(),
+                arg1,
+                arg2,
+                result: result.clone(),
+            });
+            result
+        }
+    }
+}
+
+// Slot offset and type of `field` in record type `ty`.
+unsafe fn record_field(ty: &str, field: &str) -> Option<(usize, String)> {
+    let record = records.iter().find(|record| record.0 == ty)?;
+    let mut offset = 0;
+    for i in record.1.iter() {
+        if i.name == field {
+            return Some((offset, i.ty.clone()));
+        }
+        offset += size_of(i.ty.as_str());
+    }
+    None
+}
+
 unsafe fn conditional_exp() -> Result<String> {
     let mut arg1 = relation_exp()?;
 
@@ -611,7 +1846,9 @@ unsafe fn conditional_exp() -> Result<String> {
             Err(_) => break,
         }
         let arg2 = relation_exp()?;
-        let result = temp_gen.gen();
+        check_type(arg1.as_str(), &["boolean"])?;
+        check_type(arg2.as_str(), &["boolean"])?;
+        let result = temp_gen.gen("boolean");
         quaternions.push(Quaternion {
             op,
             arg1: arg1.clone(),
@@ -634,7 +1871,9 @@ unsafe fn relation_exp() -> Result<String> {
             Err(_) => break,
         }
         let arg2 = comp_exp()?;
-        let result = temp_gen.gen();
+        check_type(arg1.as_str(), &["boolean"])?;
+        check_type(arg2.as_str(), &["boolean"])?;
+        let result = temp_gen.gen("boolean");
         quaternions.push(Quaternion {
             op,
             arg1: arg1.clone(),
@@ -648,10 +1887,38 @@ unsafe fn relation_exp() -> Result<String> {
 }
 
 unsafe fn comp_exp() -> Result<String> {
+    if let Ok(op) = terminator(Type::Keyword, Some("not")) {
+        let arg1 = comp_exp()?;
+        check_type(arg1.as_str(), &["boolean"])?;
+        let result = temp_gen.gen("boolean");
+        quaternions.push(Quaternion {
+            op,
+            arg1,
+            arg2: "_".to_string(),
+            result: result.clone(),
+        });
+        return Ok(result);
+    }
+
     let arg1 = exp()?;
-    let op = cmp_op()?;
+    let op = match cmp_op() {
+        Ok(op) => op,
+        Err(_) => return Ok(arg1),
+    };
     let arg2 = exp()?;
-    let result = temp_gen.gen();
+    // booleans can only be tested for (in)equality with each other
+    let (ty1, ty2) = (type_of(arg1.as_str()), type_of(arg2.as_str()));
+    if (ty1 == "boolean") != (ty2 == "boolean") || (ty1 == "boolean" && op != "==" && op != "<>")
+    {
+        return Err(StandardError::new(format!(
+            "line {}.\noperator `{}` cannot compare Type `{}` with Type `{}`.",
+            words[idx - 1].row,
+            op,
+            ty1,
+            ty2
+        )));
+    }
+    let result = temp_gen.gen("boolean");
     quaternions.push(Quaternion {
         op,
         arg1,
@@ -670,22 +1937,71 @@ unsafe fn cmp_op() -> Result<String> {
     Ok(val)
 }
 
+// Whether an operand is a constant rather than the name of a temp or variable.
+fn is_literal(val: &str) -> bool {
+    val == "true" || val == "false" || val.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+}
+
+// Type of an operand: a temp, a literal or a variable.
+unsafe fn type_of(val: &str) -> String {
+    if temp_gen.contains(val) {
+        return temp_gen.type_of(val);
+    }
+    if val == "true" || val == "false" {
+        return "boolean".to_string();
+    }
+    if val.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        return if val.contains('.') { "float" } else { "integer" }.to_string();
+    }
+    var_type(val).unwrap_or_default()
+}
+
+unsafe fn check_type(val: &str, ty: &[&str]) -> Result<String> {
+    let found = type_of(val);
+    if !ty.contains(&found.as_str()) {
+        return Err(StandardError::new(format!(
+            "line {}.\nexpected Type `{}`, found Type `{}`.",
+            words[idx - 1].row,
+            ty.join(", "),
+            found
+        )));
+    }
+    Ok(found)
+}
+
+// A number converts to either numeric type, a boolean only to `boolean`.
+unsafe fn check_assign(ty: &str, val: &str) -> Result<String> {
+    if ty == "boolean" {
+        check_type(val, &["boolean"])
+    } else {
+        check_type(val, &NUMERIC)
+    }
+}
+
+// Result type of an arithmetic operator.
+unsafe fn arith_type(arg1: &str, arg2: &str) -> Result<String> {
+    let ty1 = check_type(arg1, &NUMERIC)?;
+    let ty2 = check_type(arg2, &NUMERIC)?;
+    if ty1 == "float" || ty2 == "float" {
+        return Ok("float".to_string());
+    }
+    Ok("integer".to_string())
+}
+
 unsafe fn check_undef(val: &str, ty: &[&str]) -> Result<()> {
     if temp_gen.contains(val) {
         return Ok(());
     }
-    for i in params.iter().chain(vars.iter()).chain(global_vars.iter()) {
-        if i.name == val {
-            if ty.contains(&i.ty.as_str()) {
-                return Ok(());
-            } else {
-                return Err(StandardError::new(format!(
-                    "Identifier {} exists, but expected Type `{}`, found Type `{}`",
-                    val,
-                    ty.join(", "),
-                    i.ty
-                )));
-            }
+    if let Some(i) = symbol(val) {
+        if ty.contains(&i.ty.as_str()) {
+            return Ok(());
+        } else {
+            return Err(StandardError::new(format!(
+                "Identifier {} exists, but expected Type `{}`, found Type `{}`",
+                val,
+                ty.join(", "),
+                i.ty
+            )));
         }
     }
     return Err(StandardError::new(format!(
@@ -694,6 +2010,44 @@ unsafe fn check_undef(val: &str, ty: &[&str]) -> Result<()> {
     )));
 }
 
+// Rejects writes to the constant `val`.
+unsafe fn check_var(val: &str) -> Result<()> {
+    if let Some(Var { kind: Kind::Const(_), .. }) = symbol(val) {
+        return Err(StandardError::new(format!(
+            "line {}.\ncannot assign to constant `{}`.",
+            words[idx - 1].row,
+            val
+        )));
+    }
+    Ok(())
+}
+
+// The innermost declaration of `val`, searching enclosing procedures
+// outwards before the globals.
+unsafe fn symbol(val: &str) -> Option<&'static Var> {
+    let enclosing = scopes
+        .iter()
+        .rev()
+        .flat_map(|&i| proc_list[i].params.iter().chain(proc_list[i].vars.iter()));
+    params
+        .iter()
+        .chain(vars.iter())
+        .chain(enclosing)
+        .chain(global_vars.iter())
+        .find(|i| i.name == val)
+}
+
+// Returns the declared type of variable `val`.
+unsafe fn var_type(val: &str) -> Result<String> {
+    if let Some(i) = symbol(val) {
+        return Ok(i.ty.clone());
+    }
+    Err(StandardError::new(format!(
+        "Identifier {} does not exist",
+        val
+    )))
+}
+
 // unsafe fn check_mul_def(val: &str, ty: &[&str], ) -> Result<()> {
 //
 // }
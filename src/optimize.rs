@@ -0,0 +1,515 @@
+// Machine-independent optimizations, run on each procedure's control-flow
+// graph. `-O1` folds and propagates constants, regenerates each block from
+// its DAG, propagates copies, then removes the code that leaves dead; `-O2` also
+// optimizes loops and takes the result through SSA form and back, as `ssa`
+// does at any level. Returns one line per procedure telling how many
+// quaternions were eliminated.
+unsafe fn optimize(level: usize, ssa: bool) -> Vec<String> {
+    let mut report = Vec::new();
+    let visible: Vec<Scope> = (0..proc_list.len()).map(|i| Scope::new(i)).collect();
+    for (proc, scope) in proc_list.iter_mut().zip(visible.iter()) {
+        let mut cfg = Cfg::new(&proc.quaternions);
+        if ssa && level < 2 {
+            cfg = Ssa::new(&cfg, scope).destruct(&mut last_temp(&cfg));
+        }
+        if level >= 1 {
+            propagate_constants(&mut cfg, scope);
+            fold_branches(&mut cfg);
+            remove_unreachable(&mut cfg);
+            thread_jumps(&mut cfg);
+            remove_unreachable(&mut cfg);
+            eliminate_common_subexpressions(&mut cfg, scope);
+            propagate_copies(&mut cfg, scope);
+            remove_dead_temps(&mut cfg);
+            coalesce_temps(&mut cfg);
+        }
+        if level >= 2 {
+            optimize_loops(&mut cfg, scope);
+            cfg = Ssa::new(&cfg, scope).destruct(&mut last_temp(&cfg));
+            propagate_copies(&mut cfg, scope);
+            remove_dead_temps(&mut cfg);
+            coalesce_temps(&mut cfg);
+        }
+        let quads = cfg.linearize();
+        // strength reduction trades a multiplication in a loop for more
+        // quaternions around it
+        report.push(match proc.quaternions.len().checked_sub(quads.len()) {
+            Some(n) => format!("{}: {} quaternion(s) eliminated", proc.name, n),
+            None => format!("{}: {} quaternion(s) added", proc.name, quads.len() - proc.quaternions.len()),
+        });
+        proc.quaternions = quads;
+    }
+    report
+}
+
+// Declared types of the names a procedure can see. `aliased` names may
+// share their slot with another one: once the procedure or an enclosing one
+// has a `var` param, any of those params, the globals and the enclosing
+// procedures' locals may be the same variable.
+#[derive(Default)]
+struct Scope {
+    names: HashMap<String, (String, bool)>,
+    // the procedure's own variables and value params
+    locals: Vec<String>,
+}
+
+impl Scope {
+    unsafe fn new(proc: usize) -> Self {
+        let mut names = HashMap::new();
+        let mut by_ref = false;
+        for i in global_vars.iter() {
+            names.insert(i.name.clone(), (i.ty.clone(), true));
+        }
+        let mut parent = proc_list[proc].parent;
+        while let Some(p) = parent {
+            for i in proc_list[p].params.iter().chain(proc_list[p].vars.iter()) {
+                by_ref |= i.kind == Kind::Ref;
+                names.insert(i.name.clone(), (i.ty.clone(), true));
+            }
+            parent = proc_list[p].parent;
+        }
+        let mut locals = Vec::new();
+        for i in proc_list[proc].params.iter().chain(proc_list[proc].vars.iter()) {
+            by_ref |= i.kind == Kind::Ref;
+            names.insert(i.name.clone(), (i.ty.clone(), i.kind == Kind::Ref));
+            if i.kind == Kind::Var {
+                locals.push(i.name.clone());
+            }
+        }
+        if !by_ref {
+            names.values_mut().for_each(|i| i.1 = false);
+        }
+        Scope { names, locals }
+    }
+
+    fn ty(&self, name: &str) -> Option<&str> {
+        self.names.get(name).map(|i| i.0.as_str())
+    }
+
+    fn local(&self, name: &str) -> bool {
+        self.locals.iter().any(|i| i == name)
+    }
+
+    fn aliased(&self, name: &str) -> bool {
+        self.names.get(name).is_some_and(|i| i.1)
+    }
+}
+
+// Whether `arg1` and `arg2` of a quaternion are values it reads, rather
+// than aggregates, labels or `_`.
+fn reads(op: &str) -> (bool, bool) {
+    match op {
+        "+" | "-" | "*" | "/" | "%" | "<" | "<=" | ">" | ">=" | "==" | "<>" | "and" | "or"
+        | "[]=" => (true, true),
+        "=" | "neg" | "not" | "jnz" | "jez" | "jtab" | "bound" | "write" | "par" | "ret" => {
+            (true, false)
+        }
+        "=[]" | "ref" => (false, true),
+        _ => (false, false),
+    }
+}
+
+fn operands(q: &Quaternion) -> Vec<&String> {
+    let (arg1, arg2) = reads(&q.op);
+    let mut v = Vec::new();
+    if arg1 {
+        v.push(&q.arg1);
+    }
+    if arg2 {
+        v.push(&q.arg2);
+    }
+    v
+}
+
+fn operands_mut(q: &mut Quaternion) -> Vec<&mut String> {
+    let (arg1, arg2) = reads(&q.op);
+    let mut v = Vec::new();
+    if arg1 {
+        v.push(&mut q.arg1);
+    }
+    if arg2 {
+        v.push(&mut q.arg2);
+    }
+    v
+}
+
+// The scalar a quaternion assigns, if any.
+fn def(q: &Quaternion) -> Option<&str> {
+    match q.op.as_str() {
+        "=" | "+" | "-" | "*" | "/" | "%" | "<" | "<=" | ">" | ">=" | "==" | "<>" | "and" | "or"
+        | "not" | "neg" | "=[]" | "read" | "retval" => Some(q.result.as_str()),
+        _ => None,
+    }
+}
+
+// Whether `q` may assign the names `aliased` says share their slot: by
+// assigning one of them, or an element of an array one of them may be
+// bound to.
+fn assigns_aliased(q: &Quaternion, scope: &Scope) -> bool {
+    match q.op.as_str() {
+        "[]=" => scope.aliased(&q.result),
+        _ => def(q).is_some_and(|name| scope.aliased(name)),
+    }
+}
+
+// Evaluates a quaternion whose operands are all literals into a copy of the
+// result. Division by zero is left for the interpreter to report.
+fn fold(q: &mut Quaternion) {
+    let arg = |s: &str| if is_literal(s) { literal(s) } else { None };
+    let val = match (q.op.as_str(), arg(&q.arg1), arg(&q.arg2)) {
+        ("+" | "-" | "*" | "/" | "%", Some(a), Some(b)) => arith(&q.op, a, b).ok(),
+        ("<" | "<=" | ">" | ">=" | "==" | "<>", Some(a), Some(b)) => {
+            Some(Value::Boolean(compare(&q.op, a, b)))
+        }
+        ("and", Some(a), Some(b)) => Some(Value::Boolean(a.is_true() && b.is_true())),
+        ("or", Some(a), Some(b)) => Some(Value::Boolean(a.is_true() || b.is_true())),
+        ("not", Some(a), _) => Some(Value::Boolean(!a.is_true())),
+        ("neg", Some(Value::Integer(v)), _) => Some(Value::Integer(v.wrapping_neg())),
+        ("neg", Some(Value::Float(v)), _) => Some(Value::Float(-v)),
+        _ => None,
+    };
+    match val {
+        Some(Value::Float(v)) if !v.is_finite() => {}
+        Some(val) => {
+            q.op = "=".to_string();
+            q.arg1 = val.to_string();
+            q.arg2 = "_".to_string();
+        }
+        None => {}
+    }
+}
+
+// Substitutes the known constants into `q`'s operands and folds it, then
+// updates `facts` (name to literal) with what holds after it. A call may
+// change any variable, so only temps survive it, and a store into an
+// aliased array any aliased name.
+fn transfer(q: &mut Quaternion, facts: &mut HashMap<String, String>, scope: &Scope) {
+    for arg in operands_mut(q) {
+        if let Some(val) = facts.get(arg.as_str()) {
+            *arg = val.clone();
+        }
+    }
+    fold(q);
+    if q.op == "call" {
+        facts.retain(|name, _| name.starts_with('@'));
+    }
+    if assigns_aliased(q, scope) {
+        facts.retain(|name, _| !scope.aliased(name));
+    }
+    if let Some(name) = def(q) {
+        facts.remove(name);
+        if q.op == "=" && is_literal(&q.arg1) {
+            let val = match (scope.ty(name), literal(&q.arg1)) {
+                (Some(ty), Some(val)) => Some(val.cast(Value::zero(ty)).to_string()),
+                (None, _) if name.starts_with('@') => Some(q.arg1.clone()),
+                _ => None,
+            };
+            if let Some(val) = val {
+                facts.insert(name.to_string(), val);
+            }
+        }
+    }
+}
+
+// A `bound` on a constant index that is in range can never fail.
+fn in_bounds(q: &Quaternion) -> bool {
+    q.op == "bound"
+        && matches!(
+            (literal(&q.arg1), q.arg2.parse::<i64>()),
+            (Some(Value::Integer(i)), Ok(len)) if is_literal(&q.arg1) && 0 <= i && i < len
+        )
+}
+
+// Forward dataflow over the graph: a fact about a name holds on entry to a
+// block when every predecessor computed so far leaves it the same.
+fn facts_in(cfg: &Cfg, out: &[Option<HashMap<String, String>>], id: usize) -> HashMap<String, String> {
+    if id == 0 {
+        return HashMap::new();
+    }
+    let mut facts: Option<HashMap<String, String>> = None;
+    for p in cfg.blocks[id].pred.iter() {
+        if let Some(o) = &out[*p] {
+            facts = Some(match facts {
+                None => o.clone(),
+                Some(f) => f.into_iter().filter(|(k, v)| o.get(k) == Some(v)).collect(),
+            });
+        }
+    }
+    facts.unwrap_or_default()
+}
+
+// Iterates `transfer` over the graph until the facts leaving each block
+// settle, then runs it once more to rewrite the quaternions.
+fn propagate(cfg: &mut Cfg, scope: &Scope, transfer: Transfer) {
+    let mut out: Vec<Option<HashMap<String, String>>> = vec![None; cfg.blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for id in 0..cfg.blocks.len() {
+            let mut facts = facts_in(cfg, &out, id);
+            for q in cfg.blocks[id].quaternions.iter() {
+                transfer(&mut q.clone(), &mut facts, scope);
+            }
+            if out[id].as_ref() != Some(&facts) {
+                out[id] = Some(facts);
+                changed = true;
+            }
+        }
+    }
+
+    for id in 0..cfg.blocks.len() {
+        let mut facts = facts_in(cfg, &out, id);
+        for q in cfg.blocks[id].quaternions.iter_mut() {
+            transfer(q, &mut facts, scope);
+        }
+    }
+}
+
+type Transfer = fn(&mut Quaternion, &mut HashMap<String, String>, &Scope);
+
+fn propagate_constants(cfg: &mut Cfg, scope: &Scope) {
+    propagate(cfg, scope, transfer);
+    for block in cfg.blocks.iter_mut() {
+        block.quaternions.retain(|q| !in_bounds(q));
+    }
+
+    // a temp holding a constant is gone once every use has been replaced
+    let mut used: Vec<String> = Vec::new();
+    for block in cfg.blocks.iter() {
+        for q in block.quaternions.iter() {
+            used.extend(operands(q).into_iter().cloned());
+        }
+    }
+    for block in cfg.blocks.iter_mut() {
+        block.quaternions.retain(|q| {
+            !(q.op == "=" && is_literal(&q.arg1) && q.result.starts_with('@') && !used.contains(&q.result))
+        });
+    }
+}
+
+// Like `transfer`, for `copies` (name to the name it was copied from). A
+// copy into a variable of another type is a conversion, not a copy.
+fn copy_transfer(q: &mut Quaternion, copies: &mut HashMap<String, String>, scope: &Scope) {
+    for arg in operands_mut(q) {
+        if let Some(src) = copies.get(arg.as_str()) {
+            *arg = src.clone();
+        }
+    }
+    if q.op == "call" {
+        copies.retain(|name, src| name.starts_with('@') && src.starts_with('@'));
+    }
+    if assigns_aliased(q, scope) {
+        copies.retain(|i, src| !scope.aliased(i) && !scope.aliased(src));
+    }
+    if let Some(name) = def(q) {
+        copies.retain(|i, src| i != name && src != name);
+        let same_type = name.starts_with('@') || scope.ty(name).is_some() && scope.ty(name) == scope.ty(&q.arg1);
+        if q.op == "=" && !is_literal(&q.arg1) && q.arg1 != name && same_type {
+            copies.insert(name.to_string(), q.arg1.clone());
+        }
+    }
+}
+
+fn propagate_copies(cfg: &mut Cfg, scope: &Scope) {
+    propagate(cfg, scope, copy_transfer);
+}
+
+// A conditional jump or `jtab` on a constant always goes the same way.
+fn fold_branches(cfg: &mut Cfg) {
+    for block in cfg.blocks.iter_mut() {
+        let q = match block.quaternions.last_mut() {
+            Some(q) if is_literal(&q.arg1) => q,
+            _ => continue,
+        };
+        let target = match (q.op.as_str(), literal(&q.arg1)) {
+            ("jnz", Some(val)) if val.is_true() => Some(q.result.clone()),
+            ("jez", Some(val)) if !val.is_true() => Some(q.result.clone()),
+            ("jnz" | "jez", _) => None,
+            ("jtab", Some(Value::Integer(v))) => {
+                let (min, max): (i64, i64) = (q.arg2.parse().unwrap(), q.result.parse().unwrap());
+                let offset = match v >= min && v <= max {
+                    true => v.abs_diff(min),
+                    false => max.abs_diff(min) + 1,
+                };
+                Some(block.succ[offset as usize].to_string())
+            }
+            _ => continue,
+        };
+        match target {
+            Some(target) => {
+                *q = Quaternion {
+                    op: "j".to_string(),
+                    arg1: "_".to_string(),
+                    arg2: "_".to_string(),
+                    result: target,
+                }
+            }
+            None => {
+                block.quaternions.pop();
+            }
+        }
+    }
+    cfg.rebuild();
+}
+
+fn remove_unreachable(cfg: &mut Cfg) {
+    let mut reached = vec![false; cfg.blocks.len()];
+    let mut stack = vec![0];
+    while let Some(id) = stack.pop() {
+        if !reached[id] {
+            reached[id] = true;
+            stack.extend(cfg.blocks[id].succ.iter());
+        }
+    }
+    for (id, block) in cfg.blocks.iter_mut().enumerate() {
+        if !reached[id] {
+            block.quaternions.clear();
+        }
+    }
+    cfg.rebuild();
+}
+
+// Jumps to a block holding only a `j` go straight to where that one leads,
+// a `j` to the block laid out next is dropped, and a conditional jump over
+// such a `j` is inverted to take its place. Entries of a `jtab` are kept.
+fn thread_jumps(cfg: &mut Cfg) {
+    let size: usize = cfg.blocks.iter().map(|b| b.quaternions.len()).sum();
+    let entries = cfg.jump_table_entries();
+    let n = cfg.blocks.len();
+    let forward = |cfg: &Cfg, mut id: usize| {
+        for _ in 0..n {
+            let block = &cfg.blocks[id];
+            id = match block.quaternions.as_slice() {
+                [] if id != cfg.exit => id + 1,
+                [q] if q.op == "j" && !entries.contains(&id) => q.result.parse().unwrap(),
+                _ => break,
+            };
+        }
+        id
+    };
+    // the first block after `id` that is not empty
+    let next = |cfg: &Cfg, id: usize| {
+        (id + 1..n).find(|&i| i == cfg.exit || !cfg.blocks[i].quaternions.is_empty()).unwrap()
+    };
+
+    for id in 0..cfg.exit {
+        if let Some(q) = cfg.blocks[id].quaternions.last() {
+            if is_jump(&q.op) {
+                let target = forward(cfg, q.result.parse().unwrap());
+                cfg.blocks[id].quaternions.last_mut().unwrap().result = target.to_string();
+            }
+        }
+    }
+    for id in 0..cfg.exit {
+        let (op, target) = match cfg.blocks[id].quaternions.last() {
+            Some(q) if is_jump(&q.op) && !entries.contains(&id) => {
+                (q.op.clone(), q.result.parse::<usize>().unwrap())
+            }
+            _ => continue,
+        };
+        let after = next(cfg, id);
+        if forward(cfg, after) == forward(cfg, target) {
+            cfg.blocks[id].quaternions.pop();
+            continue;
+        }
+        // `jnz c, L; j M; L:` becomes `jez c, M; L:`
+        let skip = &cfg.blocks[after];
+        if op != "j"
+            && after != cfg.exit
+            && !entries.contains(&after)
+            && skip.pred == [id]
+            && skip.quaternions.len() == 1
+            && skip.quaternions[0].op == "j"
+            && forward(cfg, next(cfg, after)) == forward(cfg, target)
+        {
+            let other = skip.quaternions[0].result.clone();
+            cfg.blocks[after].quaternions.clear();
+            let q = cfg.blocks[id].quaternions.last_mut().unwrap();
+            q.op = if op == "jnz" { "jez" } else { "jnz" }.to_string();
+            q.result = other;
+        }
+    }
+    cfg.rebuild();
+    // a dropped jump may leave another one jumping to the next block
+    if cfg.blocks.iter().map(|b| b.quaternions.len()).sum::<usize>() < size {
+        thread_jumps(cfg);
+    }
+}
+
+// Temps live on leaving each block; variables may be read after the
+// procedure returns or by the procedures it calls, so only temps are sure
+// to be dead once nothing reads them.
+fn live_temps(cfg: &Cfg) -> Vec<HashSet<String>> {
+    let live = LiveVariables { names: Vec::new(), scope: &Scope::default() };
+    let solution = solve(cfg, &live);
+    solution.blocks.into_iter().map(|(_, out)| out.into_iter().filter(|i| i.starts_with('@')).collect()).collect()
+}
+
+// Drops quaternions that only compute a temp nobody reads. A division is
+// kept unless its divisor is a nonzero constant, as it may fail at runtime.
+fn remove_dead_temps(cfg: &mut Cfg) {
+    loop {
+        let live_out = live_temps(cfg);
+        let mut removed = false;
+        for (block, mut live) in cfg.blocks.iter_mut().zip(live_out) {
+            let mut keep = vec![true; block.quaternions.len()];
+            for (i, q) in block.quaternions.iter().enumerate().rev() {
+                let safe = !(q.op == "/" || q.op == "%")
+                    || (is_literal(&q.arg2) && literal(&q.arg2).is_some_and(|v| v.is_true()));
+                if let Some(name) = def(q) {
+                    if name.starts_with('@') && !live.contains(name) && safe {
+                        keep[i] = false;
+                        removed = true;
+                        continue;
+                    }
+                    live.remove(name);
+                }
+                live.extend(operands(q).into_iter().filter(|i| i.starts_with('@')).cloned());
+            }
+            let mut keep = keep.into_iter();
+            block.quaternions.retain(|_| keep.next().unwrap());
+        }
+        if !removed {
+            break;
+        }
+    }
+}
+
+fn eliminate_common_subexpressions(cfg: &mut Cfg, scope: &Scope) {
+    let live_out = live_temps(cfg);
+    let mut temps = last_temp(cfg);
+    for (block, live) in cfg.blocks.iter_mut().zip(live_out) {
+        block.quaternions = Dag::new(&block.quaternions, &live, scope, &mut temps).quaternions;
+    }
+}
+
+// `(op, a, b, @t), (=, @t, _, x)` computes straight into `x` when nothing
+// else reads `@t`; storing into `x` converts the value either way. A temp
+// `x`, which no alias can read, may also be copied into further down, as
+// long as nothing in between reads or assigns it.
+fn coalesce_temps(cfg: &mut Cfg) {
+    let live_out = live_temps(cfg);
+    for (block, live) in cfg.blocks.iter_mut().zip(live_out) {
+        let quads = &mut block.quaternions;
+        let mut i = 0;
+        while i + 1 < quads.len() {
+            let temp = quads[i].result.clone();
+            if def(&quads[i]).is_some_and(|i| i.starts_with('@')) && !live.contains(&temp) {
+                let reads: Vec<usize> =
+                    (i + 1..quads.len()).filter(|&j| operands(&quads[j]).contains(&&temp)).collect();
+                if let [j] = reads[..] {
+                    let x = quads[j].result.clone();
+                    if quads[j].op == "="
+                        && quads[j].arg1 == temp
+                        && (j == i + 1 || x.starts_with('@'))
+                        && quads[i + 1..j].iter().all(|q| !operands(q).contains(&&x) && def(q) != Some(x.as_str()))
+                    {
+                        quads[i].result = x;
+                        quads.remove(j);
+                    }
+                }
+            }
+            i += 1;
+        }
+    }
+}
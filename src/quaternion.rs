@@ -0,0 +1,8 @@
+// `(op, arg1, arg2, result)`, with `_` for an unused field.
+#[derive(Clone, Debug)]
+pub struct Quaternion {
+    pub op: String,
+    pub arg1: String,
+    pub arg2: String,
+    pub result: String,
+}
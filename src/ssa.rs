@@ -0,0 +1,405 @@
+// `result = phi(args)` at the start of a block: `args` has one version of
+// `name` per predecessor, in the order of the block's `pred`.
+#[derive(Clone, Debug)]
+struct Phi {
+    pub name: String,
+    pub result: String,
+    pub args: Vec<String>,
+}
+
+// A procedure in static single assignment form: every assignment defines a
+// new version `x.N` of its name, a bare `x` being the value on entry, and
+// `phis` merge the versions reaching each block. A `call` gives every
+// name `assigned` says it may assign a new version.
+struct Ssa {
+    pub cfg: Cfg,
+    pub phis: Vec<Vec<Phi>>,
+}
+
+impl Ssa {
+    // Cytron et al.: phis go on the iterated dominance frontier of the
+    // blocks assigning a name, for names read in some block before it is
+    // assigned there (semi-pruned form), then a walk down the dominator
+    // tree renames each assignment and read.
+    pub fn new(cfg: &Cfg, scope: &Scope) -> Self {
+        let with_entry;
+        let cfg = match cfg.blocks[0].pred.is_empty() {
+            true => cfg,
+            false => {
+                with_entry = cfg.with_entry();
+                &with_entry
+            }
+        };
+        let dom = cfg.dominators();
+        let frontiers = dom.frontiers(cfg);
+        let mut renaming = Renaming {
+            children: dom.children(),
+            scope,
+            tracked: names_in(cfg),
+            counter: HashMap::new(),
+            stacks: HashMap::new(),
+        };
+        let mut sites: HashMap<&str, Vec<usize>> = HashMap::new();
+        let mut global: Vec<&str> = Vec::new();
+        for (id, block) in cfg.blocks.iter().enumerate() {
+            let mut defined: Vec<&str> = Vec::new();
+            for q in block.quaternions.iter() {
+                for name in operands(q).into_iter().filter(|i| !is_literal(i)) {
+                    if !defined.contains(&name.as_str()) && !global.contains(&name.as_str()) {
+                        global.push(name.as_str());
+                    }
+                }
+                defined.extend(assigned(q, &renaming.tracked, scope));
+            }
+            for name in defined {
+                let site = sites.entry(name).or_default();
+                if !site.contains(&id) {
+                    site.push(id);
+                }
+            }
+        }
+
+        let mut phis: Vec<Vec<Phi>> = vec![Vec::new(); cfg.blocks.len()];
+        for name in global {
+            let mut work = sites.get(name).cloned().unwrap_or_default();
+            while let Some(id) = work.pop() {
+                for &f in frontiers[id].iter() {
+                    if phis[f].iter().any(|phi| phi.name == name) {
+                        continue;
+                    }
+                    phis[f].push(Phi {
+                        name: name.to_string(),
+                        result: name.to_string(),
+                        args: vec![name.to_string(); cfg.blocks[f].pred.len()],
+                    });
+                    if !sites[name].contains(&f) {
+                        work.push(f);
+                    }
+                }
+            }
+        }
+
+        let mut ssa = Ssa { cfg: cfg.clone(), phis };
+        renaming.rename(&mut ssa, 0);
+        ssa
+    }
+}
+
+impl Ssa {
+    // Back out of SSA form. A variable's versions all go back to its slot:
+    // calls, nested procedures and the `var` params bound to it read and
+    // write the slot itself, and each store converts to its declared type,
+    // so its phis are no-ops. A temp's versions become temps of their own:
+    // a phi shares one with each arg never live at the same time as it, and
+    // copies the others on the edges into its block, at the end of a
+    // predecessor with no other successor and in a block of its own
+    // splitting the edge otherwise. `jtab` entries must stay a single `j`,
+    // so the edges out of them are split too.
+    pub fn destruct(self, temps: &mut usize) -> Cfg {
+        let mut renamed = HashMap::new();
+        let interfering = self.interference();
+        let mut classes: Vec<Vec<String>> = Vec::new();
+        let mut class: HashMap<String, usize> = HashMap::new();
+        for phi in self.phis.iter().flatten().filter(|phi| phi.name.starts_with('@')) {
+            for version in phi.args.iter().filter(|i| versioned(i)) {
+                let mut ids = [&phi.result, version].map(|i| match class.get(i) {
+                    Some(&id) => id,
+                    None => {
+                        class.insert(i.clone(), classes.len());
+                        classes.push(vec![i.clone()]);
+                        classes.len() - 1
+                    }
+                });
+                ids.sort();
+                let [a, b] = ids;
+                let pairs = classes[a].iter().flat_map(|i| classes[b].iter().map(move |j| (i.clone(), j.clone())));
+                let apart = pairs.into_iter().any(|pair| interfering.contains(&pair));
+                if a != b && !apart {
+                    for i in std::mem::take(&mut classes[b]) {
+                        class.insert(i.clone(), a);
+                        classes[a].push(i);
+                    }
+                }
+            }
+        }
+        for members in classes.iter().filter(|i| !i.is_empty()) {
+            let temp = fresh(temps);
+            for version in members {
+                renamed.insert(version.clone(), temp.clone());
+            }
+        }
+
+        let Ssa { mut cfg, phis } = self;
+        for q in cfg.blocks.iter_mut().flat_map(|b| b.quaternions.iter_mut()) {
+            for name in [&mut q.arg1, &mut q.arg2, &mut q.result] {
+                *name = unversioned(name, &mut renamed, temps);
+            }
+        }
+
+        // the exit reads nothing, so its phis need no copies
+        let mut edges = Vec::new();
+        for (b, block) in cfg.blocks.iter().enumerate().filter(|(b, _)| *b != cfg.exit) {
+            for (i, &p) in block.pred.iter().enumerate() {
+                let mut copies = Vec::new();
+                for phi in phis[b].iter() {
+                    let result = unversioned(&phi.result, &mut renamed, temps);
+                    let arg = unversioned(&phi.args[i], &mut renamed, temps);
+                    // a bare temp was never assigned on the way in
+                    if arg != result && !(arg == phi.name && arg.starts_with('@')) {
+                        copies.push((result, arg));
+                    }
+                }
+                if !copies.is_empty() {
+                    edges.push((p, b, sequentialize(copies, temps)));
+                }
+            }
+        }
+
+        // blocks splitting edges go right before the exit, which the block
+        // laid out last must now jump to
+        let entries = cfg.jump_table_entries();
+        let exit = cfg.exit;
+        let splits = edges.iter().filter(|(p, _, _)| cfg.blocks[*p].succ.len() > 1 || entries.contains(p)).count();
+        if splits > 0 {
+            for q in cfg.blocks.iter_mut().flat_map(|b| b.quaternions.iter_mut()) {
+                if is_jump(&q.op) && q.result == exit.to_string() {
+                    q.result = (exit + splits).to_string();
+                }
+            }
+            let last = &mut cfg.blocks[exit - 1].quaternions;
+            if !last.last().is_some_and(|q| q.op == "j" || q.op == "ret") {
+                last.push(jump(exit + splits));
+            }
+        }
+        let mut split = Vec::new();
+        for (p, b, mut copies) in edges {
+            let block = &mut cfg.blocks[p];
+            if block.succ.len() == 1 && !entries.contains(&p) {
+                let at = match block.quaternions.last() {
+                    Some(q) if is_jump(&q.op) => block.quaternions.len() - 1,
+                    _ => block.quaternions.len(),
+                };
+                block.quaternions.splice(at..at, copies);
+                continue;
+            }
+            let id = exit + split.len();
+            match block.quaternions.last_mut() {
+                Some(q) if is_jump(&q.op) && q.result == b.to_string() => q.result = id.to_string(),
+                _ => block.quaternions.push(jump(id)),
+            }
+            copies.push(jump(b));
+            split.push(BasicBlock { quaternions: copies, ..Default::default() });
+        }
+        for (i, block) in split.into_iter().enumerate() {
+            cfg.blocks.insert(exit + i, block);
+        }
+        cfg.exit += splits;
+        cfg.rebuild();
+        cfg
+    }
+}
+
+impl Ssa {
+    // Pairs of temp versions live at the same time, which cannot share a
+    // temp out of SSA form. A phi's args are live at the end of their
+    // predecessors and its result from the start of its block.
+    fn interference(&self) -> HashSet<(String, String)> {
+        let mut live_in = vec![HashSet::new(); self.cfg.blocks.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in (0..self.cfg.blocks.len()).rev() {
+                let live = self.live_in(id, &live_in, &mut HashSet::new());
+                if live != live_in[id] {
+                    live_in[id] = live;
+                    changed = true;
+                }
+            }
+        }
+        let mut pairs = HashSet::new();
+        for id in 0..self.cfg.blocks.len() {
+            self.live_in(id, &live_in, &mut pairs);
+        }
+        pairs
+    }
+
+    // Walks a block backwards from what its successors need, adding every
+    // version defined while another is live to `pairs`.
+    fn live_in(
+        &self,
+        id: usize,
+        live_in: &[HashSet<String>],
+        pairs: &mut HashSet<(String, String)>,
+    ) -> HashSet<String> {
+        let block = &self.cfg.blocks[id];
+        let mut live = HashSet::new();
+        for &succ in block.succ.iter() {
+            live.extend(live_in[succ].iter().cloned());
+            let i = self.cfg.blocks[succ].pred.iter().position(|p| *p == id).unwrap();
+            live.extend(self.phis[succ].iter().map(|phi| phi.args[i].clone()).filter(|i| versioned(i)));
+        }
+        let mut interfere = |version: &str, live: &HashSet<String>| {
+            for i in live.iter().filter(|i| *i != version) {
+                pairs.insert((version.to_string(), i.clone()));
+                pairs.insert((i.clone(), version.to_string()));
+            }
+        };
+        for q in block.quaternions.iter().rev() {
+            if let Some(version) = def(q).filter(|i| versioned(i)) {
+                interfere(version, &live);
+                live.remove(version);
+            }
+            live.extend(operands(q).into_iter().filter(|i| versioned(i)).cloned());
+        }
+        // a block's phis all take their values at once
+        let results: Vec<&String> = self.phis[id].iter().map(|phi| &phi.result).filter(|i| versioned(i)).collect();
+        live.extend(results.iter().map(|i| i.to_string()));
+        for version in results.iter() {
+            interfere(version, &live);
+        }
+        for version in results {
+            live.remove(version);
+        }
+        live
+    }
+}
+
+// A version of a temp, which unlike a variable's may get a temp of its own.
+fn versioned(name: &str) -> bool {
+    name.starts_with('@') && name.contains('.')
+}
+
+// The name a version stands for once out of SSA form: the variable itself,
+// or a fresh temp for each version of a temp.
+fn unversioned(version: &str, renamed: &mut HashMap<String, String>, temps: &mut usize) -> String {
+    let name = match version.rsplit_once('.') {
+        Some((name, n)) if !is_literal(version) && n.parse::<usize>().is_ok() => name,
+        _ => return version.to_string(),
+    };
+    match name.starts_with('@') {
+        true => renamed.entry(version.to_string()).or_insert_with(|| fresh(temps)).clone(),
+        false => name.to_string(),
+    }
+}
+
+fn jump(target: usize) -> Quaternion {
+    Quaternion {
+        op: "j".to_string(),
+        arg1: "_".to_string(),
+        arg2: "_".to_string(),
+        result: target.to_string(),
+    }
+}
+
+// Orders the `(result, arg)` copies of a parallel copy so none overwrites a
+// name another one still reads, saving a name first to break a cycle.
+fn sequentialize(mut copies: Vec<(String, String)>, temps: &mut usize) -> Vec<Quaternion> {
+    let copy = |arg: &str, result: &str| Quaternion {
+        op: "=".to_string(),
+        arg1: arg.to_string(),
+        arg2: "_".to_string(),
+        result: result.to_string(),
+    };
+    let mut quads = Vec::new();
+    while !copies.is_empty() {
+        match copies.iter().position(|(result, _)| copies.iter().all(|(_, arg)| arg != result)) {
+            Some(i) => {
+                let (result, arg) = copies.remove(i);
+                quads.push(copy(&arg, &result));
+            }
+            None => {
+                let (name, saved) = (copies[0].0.clone(), fresh(temps));
+                quads.push(copy(&name, &saved));
+                for c in copies.iter_mut().filter(|c| c.1 == name) {
+                    c.1 = saved.clone();
+                }
+            }
+        }
+    }
+    quads
+}
+
+struct Renaming<'a> {
+    children: Vec<Vec<usize>>,
+    scope: &'a Scope,
+    // every name read or assigned
+    tracked: Vec<String>,
+    counter: HashMap<String, usize>,
+    // the versions in effect along the current path down the tree
+    stacks: HashMap<String, Vec<String>>,
+}
+
+impl<'a> Renaming<'a> {
+    fn current(&self, name: &str) -> String {
+        match self.stacks.get(name).and_then(|s| s.last()) {
+            Some(version) => version.clone(),
+            None => name.to_string(),
+        }
+    }
+
+    fn fresh(&mut self, name: &str, pushed: &mut Vec<String>) -> String {
+        let n = self.counter.entry(name.to_string()).or_insert(0);
+        *n += 1;
+        let version = format!("{}.{}", name, n);
+        self.stacks.entry(name.to_string()).or_default().push(version.clone());
+        pushed.push(name.to_string());
+        version
+    }
+
+    fn rename(&mut self, ssa: &mut Ssa, id: usize) {
+        let mut pushed = Vec::new();
+        for i in 0..ssa.phis[id].len() {
+            let name = ssa.phis[id][i].name.clone();
+            ssa.phis[id][i].result = self.fresh(&name, &mut pushed);
+        }
+        for i in 0..ssa.cfg.blocks[id].quaternions.len() {
+            let q = &mut ssa.cfg.blocks[id].quaternions[i];
+            for arg in operands_mut(q) {
+                *arg = self.current(arg);
+            }
+            let names: Vec<String> = assigned(q, &self.tracked, self.scope).into_iter().map(|i| i.to_string()).collect();
+            for name in names {
+                let version = self.fresh(&name, &mut pushed);
+                let q = &mut ssa.cfg.blocks[id].quaternions[i];
+                if def(q) == Some(name.as_str()) {
+                    q.result = version;
+                }
+            }
+        }
+        for &succ in ssa.cfg.blocks[id].succ.iter() {
+            let i = ssa.cfg.blocks[succ].pred.iter().position(|p| *p == id).unwrap();
+            for phi in ssa.phis[succ].iter_mut() {
+                phi.args[i] = self.current(&phi.name);
+            }
+        }
+        for child in self.children[id].clone() {
+            self.rename(ssa, child);
+        }
+        for name in pushed {
+            self.stacks.get_mut(&name).unwrap().pop();
+        }
+    }
+}
+
+impl Display for Ssa {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let ids = |list: &[usize]| match list.is_empty() {
+            true => "-".to_string(),
+            false => list.iter().map(|i| format!("B{}", i)).collect::<Vec<_>>().join(" "),
+        };
+        for (id, block) in self.cfg.blocks.iter().enumerate() {
+            match id == self.cfg.exit {
+                true => writeln!(f, "B{}: exit, pred: {}", id, ids(&block.pred))?,
+                false => writeln!(f, "B{}: pred: {}, succ: {}", id, ids(&block.pred), ids(&block.succ))?,
+            }
+            for phi in self.phis[id].iter() {
+                let args: Vec<String> = block.pred.iter().zip(phi.args.iter()).map(|(p, a)| format!("B{}: {}", p, a)).collect();
+                writeln!(f, "    {} = phi({})", phi.result, args.join(", "))?;
+            }
+            for i in block.quaternions.iter() {
+                writeln!(f, "    ({}, {}, {}, {})", i.op, i.arg1, i.arg2, i.result)?;
+            }
+        }
+        Ok(())
+    }
+}
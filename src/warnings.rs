@@ -0,0 +1,140 @@
+// Checks over the quaternions as generated, before any optimization may
+// remove what they look at. `allow` lists the kinds of warning not to give.
+unsafe fn warnings(allow: &[String]) -> Vec<String> {
+    let mut found = Vec::new();
+    if !allow.iter().any(|i| i == "uninitialized") {
+        for proc in 0..proc_list.len() {
+            found.extend(uninitialized(proc));
+        }
+    }
+    if !allow.iter().any(|i| i == "unused") {
+        found.extend(unused());
+    }
+    found
+}
+
+// Scalars read where the value they have on entry may still reach: a
+// procedure's own variables, and the globals when it is the entry point.
+// Passing one as a `var` param is no read, the callee may assign it.
+unsafe fn uninitialized(proc: usize) -> Vec<String> {
+    let p = &proc_list[proc];
+    let own = |name: &str| p.params.iter().chain(p.vars.iter()).any(|i| i.name == name);
+    let mut fresh: Vec<&str> =
+        p.vars.iter().filter(|i| i.kind == Kind::Var && is_scalar(&i.ty)).map(|i| i.name.as_str()).collect();
+    if proc == entry {
+        fresh.extend(
+            global_vars
+                .iter()
+                .filter(|i| i.kind == Kind::Var && is_scalar(&i.ty) && !own(&i.name))
+                .map(|i| i.name.as_str()),
+        );
+    }
+    if fresh.is_empty() {
+        return Vec::new();
+    }
+
+    let cfg = Cfg::new(&p.quaternions);
+    let scope = Scope::new(proc);
+    let solution = solve(&cfg, &EntryValues(ReachingDefinitions::new(&cfg, &scope)));
+    let mut read: Vec<&str> = Vec::new();
+    let quads = cfg.blocks.iter().flat_map(|b| b.quaternions.iter());
+    for (q, (before, _)) in quads.zip(solution.quaternions.iter()).filter(|(q, _)| q.op != "ref") {
+        for name in operands(q) {
+            let name = match fresh.iter().find(|i| **i == name) {
+                Some(name) => *name,
+                None => continue,
+            };
+            if before.contains(&(name.to_string(), ENTRY)) && !read.contains(&name) {
+                read.push(name);
+            }
+        }
+    }
+    read.iter().map(|i| format!("warning: variable `{}` may be used uninitialized in `{}`", i, p.name)).collect()
+}
+
+// The definitions of the values names have on entry that reach a point
+// without anything on the way that may assign them, a call for instance.
+struct EntryValues<'a>(ReachingDefinitions<'a>);
+
+impl<'a> Analysis for EntryValues<'a> {
+    type Fact = BTreeSet<(String, usize)>;
+
+    fn forward(&self) -> bool {
+        true
+    }
+
+    fn boundary(&self) -> Self::Fact {
+        self.0.boundary()
+    }
+
+    fn top(&self) -> Self::Fact {
+        self.0.top()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        self.0.meet(fact, other)
+    }
+
+    fn transfer(&self, q: &Quaternion, _: usize, fact: &mut Self::Fact) {
+        for name in assigned(q, &self.0.names, self.0.scope) {
+            fact.remove(&(name.to_string(), ENTRY));
+        }
+    }
+
+    fn show(&self, fact: &Self::Fact) -> String {
+        self.0.show(fact)
+    }
+}
+
+// Variables nothing reads, each name resolved to the declaration it
+// refers to where it appears.
+unsafe fn unused() -> Vec<String> {
+    let mut read: HashSet<(Option<usize>, &str)> = HashSet::new();
+    let mut written: HashSet<(Option<usize>, &str)> = HashSet::new();
+    for (i, proc) in proc_list.iter().enumerate() {
+        for q in proc.quaternions.iter() {
+            for name in [&q.arg1, &q.arg2] {
+                if let Some(owner) = declaring(i, name) {
+                    read.insert((owner, name));
+                }
+            }
+            if let Some(owner) = declaring(i, &q.result) {
+                written.insert((owner, &q.result));
+            }
+        }
+    }
+
+    let mut found = Vec::new();
+    let declared = global_vars
+        .iter()
+        .map(|i| (None, i))
+        .chain(proc_list.iter().enumerate().flat_map(|(p, proc)| proc.vars.iter().map(move |i| (Some(p), i))));
+    for (owner, var) in declared.filter(|(_, i)| is_stored(i)) {
+        let key = (owner, var.name.as_str());
+        if read.contains(&key) {
+            continue;
+        }
+        let what = match written.contains(&key) {
+            true => format!("variable `{}` is assigned but never used", var.name),
+            false => format!("unused variable `{}`", var.name),
+        };
+        match owner {
+            Some(p) => found.push(format!("warning: {} in `{}`", what, proc_list[p].name)),
+            None => found.push(format!("warning: {}", what)),
+        }
+    }
+    found
+}
+
+// The procedure declaring what `name` refers to inside `proc`, `None` for
+// a global, if it is a declared name at all.
+unsafe fn declaring(proc: usize, name: &str) -> Option<Option<usize>> {
+    let mut scope = Some(proc);
+    while let Some(p) = scope {
+        if proc_list[p].params.iter().chain(proc_list[p].vars.iter()).any(|i| i.name == name) {
+            return Some(Some(p));
+        }
+        scope = proc_list[p].parent;
+    }
+    global_vars.iter().any(|i| i.name == name).then_some(None)
+}
@@ -0,0 +1,22 @@
+// Helpers the test files share.
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+pub fn sample(name: &str) -> String {
+    format!("{}/resource/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+// Everything the compiler prints for `path`, with `3` for every `read`.
+pub fn compile(path: &str, args: &[&str]) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_syntax_directed_translation"))
+        .arg(path)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // the program may finish before reading all of it
+    let _ = child.stdin.take().unwrap().write_all("3\n".repeat(16).as_bytes());
+    let output = child.wait_with_output().unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
@@ -0,0 +1,107 @@
+// Checks the forms `--emit` prints: the control-flow graph as text and as
+// Graphviz, the DAG of each block, SSA form and the data-flow facts.
+mod common;
+
+use common::{compile, sample};
+
+fn emit(name: &str, form: &str) -> Vec<String> {
+    compile(&sample(name), &["--emit", form]).lines().map(|i| i.to_string()).collect()
+}
+
+// The lines printed for block `id` of the first procedure, up to the next one.
+fn block(lines: &[String], id: &str) -> Vec<String> {
+    lines
+        .iter()
+        .skip_while(|i| !i.starts_with(&format!("{}:", id)))
+        .skip(1)
+        .take_while(|i| i.starts_with(' '))
+        .map(|i| i.trim().to_string())
+        .collect()
+}
+
+#[test]
+fn cfg_lists_blocks_and_their_edges() {
+    let lines = emit("c22.txt", "cfg");
+    assert_eq!(lines[0], "cfg Main:");
+    // the loop test, jumping out to block 3 when it fails
+    assert!(lines.contains(&"B1: pred: B0 B2, succ: B3 B2".to_string()));
+    assert_eq!(block(&lines, "B1"), ["(<, i, n, @t3)", "(jez, @t3, _, 3)"]);
+    assert_eq!(lines.last().unwrap(), "B10: exit, pred: B4 B5 B6");
+    // instead of the symbol tables and quaternions
+    assert!(!lines.iter().any(|i| i == "global:" || i == "quaternions:"));
+}
+
+#[test]
+fn cfg_dot_writes_one_digraph_per_procedure() {
+    let lines = emit("c23.txt", "cfg-dot");
+    let graphs: Vec<&String> = lines.iter().filter(|i| i.starts_with("digraph")).collect();
+    assert_eq!(graphs, ["digraph \"fill\" {", "digraph \"main\" {"]);
+    assert_eq!(lines.iter().filter(|i| *i == "}").count(), 2);
+
+    let lines = emit("c22.txt", "cfg-dot");
+    assert!(lines.contains(&"    B1 -> B3 [label=\"false\"];".to_string()));
+    assert!(lines.contains(&"    B1 -> B2 [label=\"true\"];".to_string()));
+    assert!(lines.contains(&"    B10 [label=\"exit\", shape=ellipse];".to_string()));
+}
+
+#[test]
+fn dag_gives_each_value_one_node() {
+    let lines = emit("c22.txt", "dag");
+    assert_eq!(lines[0], "dag Main:");
+    let b0 = block(&lines, "B0");
+    // `s` and `i` both get the constant 0, and `t` the sum held in `@t2`
+    assert!(b0.contains(&"n1: 0 -> s, i".to_string()));
+    assert!(b0.contains(&"n6: (+, n4, n5) -> @t2, t".to_string()));
+    assert!(block(&lines, "B2").contains(&"n4: (+, n3, n2) -> @t5, s".to_string()));
+}
+
+#[test]
+fn ssa_assigns_each_version_once() {
+    let lines = emit("c22.txt", "ssa");
+    assert_eq!(lines[0], "ssa Main:");
+    let b1 = block(&lines, "B1");
+    assert!(b1.contains(&"i.2 = phi(B0: i.1, B2: i.3)".to_string()));
+    assert!(b1.contains(&"s.2 = phi(B0: s.1, B2: s.3)".to_string()));
+    let mut versions: Vec<&str> = lines
+        .iter()
+        .filter_map(|i| match i.trim().split_once(" = phi(") {
+            Some((result, _)) => Some(result),
+            None => i.trim().strip_suffix(')').and_then(|i| i.rsplit(", ").next()),
+        })
+        .filter(|i| i.contains('.'))
+        .collect();
+    let n = versions.len();
+    versions.sort();
+    versions.dedup();
+    assert_eq!(versions.len(), n);
+
+    // a bare name is the value on entry; a call may assign what it is
+    // passed by reference
+    let lines = emit("c23.txt", "ssa");
+    let main: Vec<String> = lines.iter().skip_while(|i| *i != "ssa main:").cloned().collect();
+    assert!(block(&main, "B3").contains(&"i.2 = phi(B1: i, B2: i.1)".to_string()));
+    let b3 = block(&main, "B3");
+    let call = b3.iter().position(|i| i == "(call, fill, 1, _)").unwrap();
+    assert_eq!(b3[call - 1], "(ref, m, _, _)");
+    assert_eq!(b3[call + 1], "(write, m.1, _, _)");
+}
+
+#[test]
+fn dataflow_lists_the_facts_at_each_quaternion() {
+    let lines = emit("c22.txt", "dataflow");
+    assert_eq!(lines[0], "dataflow Main:");
+    let at = lines.iter().position(|i| i == "6: (<, i, n, @t3)").unwrap();
+    // the loop test is reached from before the loop and from its body
+    let reaching = lines[at + 1].trim().strip_prefix("reaching: ").unwrap();
+    assert!(reaching.split(", ").any(|i| i == "i@5") && reaching.split(", ").any(|i| i == "i@12"));
+    assert_eq!(lines[at + 2].trim(), "live: @t3, i, n, s, t");
+    assert_eq!(lines[at + 3].trim(), "available: 4 * 2, @t1 + 1");
+    // nothing is assigned yet before the first quaternion
+    assert_eq!(lines[2].trim(), "reaching: i@entry, n@entry, s@entry, t@entry");
+}
+
+#[test]
+fn emit_expects_a_known_form() {
+    let lines = emit("c22.txt", "quads");
+    assert_eq!(lines, ["`--emit` expects one of: cfg, cfg-dot, dag, ssa, dataflow"]);
+}
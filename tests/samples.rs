@@ -0,0 +1,107 @@
+// Runs the sample programs through the compiler at each optimization level
+// and through SSA form and back: neither may change what a program prints,
+// and the samples below check that the optimizations they exercise do take
+// place.
+mod common;
+
+use common::{compile, sample};
+
+const LEVELS: [&str; 3] = ["-O0", "-O1", "-O2"];
+
+// What the program printed when run, without the optimization report.
+fn run(path: &str, args: &[&str]) -> Vec<String> {
+    compile(path, &[&["--run"], args].concat())
+        .lines()
+        .skip_while(|i| !i.starts_with("entry:"))
+        .skip(1)
+        .filter(|i| !i.contains(" quaternion(s) "))
+        .map(|i| i.to_string())
+        .collect()
+}
+
+// The quaternions listed for procedure `proc`, without their indices.
+fn quaternions(path: &str, proc: &str, level: &str) -> Vec<String> {
+    compile(path, &[level])
+        .lines()
+        .skip_while(|i| *i != format!("procedure {}:", proc))
+        .skip_while(|i| *i != "quaternions:")
+        .skip(1)
+        .take_while(|i| i.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|i| i.split_once(": ").unwrap().1.to_string())
+        .collect()
+}
+
+// Every `resource/c*.txt`.
+fn samples() -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(sample(""))
+        .unwrap()
+        .map(|i| i.unwrap().file_name().into_string().unwrap())
+        .filter(|i| i.starts_with('c') && i.ends_with(".txt"))
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn optimizing_keeps_what_samples_print() {
+    for name in samples().iter() {
+        let expected = run(&sample(name), &["-O0"]);
+        for level in LEVELS[1..].iter() {
+            assert_eq!(run(&sample(name), &[*level]), expected, "{} at {}", name, level);
+        }
+    }
+}
+
+#[test]
+fn ssa_round_trip_keeps_what_samples_print() {
+    for name in samples().iter() {
+        let expected = run(&sample(name), &["-O0"]);
+        assert_eq!(run(&sample(name), &["-O0", "--ssa"]), expected, "{}", name);
+    }
+}
+
+#[test]
+fn constants_are_folded() {
+    let quads = quaternions(&sample("c22.txt"), "Main", "-O1");
+    assert!(quads.contains(&"(=, 9, _, t)".to_string()));
+    // the `case` on `t` always takes the same arm
+    assert!(quads.contains(&"(write, 9, _, _)".to_string()));
+    assert!(!quads.iter().any(|q| q.starts_with("(==,") || q.starts_with("(write, 1,")));
+}
+
+#[test]
+fn jump_tables_keep_their_entries() {
+    for level in LEVELS.iter() {
+        let quads = quaternions(&sample("c15.txt"), "main", level);
+        let at = quads.iter().position(|q| q == "(jtab, i, 1, 5)").unwrap();
+        // one entry per value from 1 to 5, then the default
+        assert!(quads[at + 1..at + 7].iter().all(|q| q.starts_with("(j, _, _,")), "{}", level);
+    }
+}
+
+#[test]
+fn loop_invariants_are_hoisted() {
+    let quads = quaternions(&sample("c22.txt"), "Main", "-O2");
+    let invariant = quads.iter().position(|q| q.starts_with("(*, n, 9,")).unwrap();
+    let test = quads.iter().position(|q| q.starts_with("(<, i, n,")).unwrap();
+    assert!(invariant < test);
+    assert_eq!(run(&sample("c22.txt"), &["-O2"]), ["81", "9"]);
+}
+
+#[test]
+fn induction_variables_are_strength_reduced() {
+    let quads = quaternions(&sample("c15.txt"), "main", "-O2");
+    let product = quads.iter().find(|q| q.starts_with("(*, i, 100,")).unwrap();
+    let temp = product.trim_end_matches(')').rsplit(", ").next().unwrap();
+    assert!(quads.contains(&format!("(+, {}, 100, {})", temp, temp)));
+    // the multiplication only happens before the loop
+    let test = quads.iter().position(|q| q.starts_with("(<=, i, 3,")).unwrap();
+    assert!(!quads[test..].iter().any(|q| q.starts_with("(*,")));
+}
+
+#[test]
+fn stores_into_arrays_reach_var_params_bound_to_them() {
+    for level in LEVELS.iter() {
+        assert_eq!(run(&sample("c20.txt"), &[*level]), ["7", "9", "8", "8", "9", "18", "4"], "{}", level);
+    }
+}
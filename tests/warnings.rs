@@ -0,0 +1,50 @@
+// Checks the warnings given before the symbol tables, and that `--allow`
+// turns each kind off.
+mod common;
+
+use common::{compile, sample};
+
+fn warnings(name: &str, args: &[&str]) -> Vec<String> {
+    compile(&sample(name), args).lines().filter(|i| i.starts_with("warning:")).map(|i| i.to_string()).collect()
+}
+
+const UNINITIALIZED: [&str; 2] = [
+    "warning: variable `i` may be used uninitialized in `main`",
+    "warning: variable `total` may be used uninitialized in `main`",
+];
+
+const UNUSED: [&str; 3] = [
+    "warning: unused variable `spare`",
+    "warning: variable `kept` is assigned but never used in `main`",
+    "warning: unused variable `unread` in `main`",
+];
+
+#[test]
+fn warnings_come_before_the_symbol_tables() {
+    // `m` is only passed by reference, which assigns it
+    let output = compile(&sample("c23.txt"), &[]);
+    let lines: Vec<&str> = output.lines().take(6).collect();
+    assert_eq!(lines[..5], [&UNINITIALIZED[..], &UNUSED[..]].concat()[..]);
+    assert_eq!(lines[5], "global:");
+}
+
+#[test]
+fn allow_turns_each_kind_off() {
+    assert_eq!(warnings("c23.txt", &["--allow", "uninitialized"]), UNUSED);
+    assert_eq!(warnings("c23.txt", &["--allow", "unused"]), UNINITIALIZED);
+    assert!(warnings("c23.txt", &["--allow", "unused", "--allow", "uninitialized"]).is_empty());
+}
+
+#[test]
+fn allow_expects_a_known_warning() {
+    let output = compile(&sample("c23.txt"), &["--allow", "everything"]);
+    assert_eq!(output.trim(), "`--allow` expects one of: uninitialized, unused");
+}
+
+#[test]
+fn samples_only_leave_variables_unused() {
+    for name in ["c15.txt", "c20.txt", "c22.txt"] {
+        assert!(warnings(name, &[]).iter().all(|i| !i.contains("uninitialized")), "{}", name);
+    }
+    assert_eq!(warnings("c4.txt", &[]), ["warning: variable `b` is assigned but never used"]);
+}